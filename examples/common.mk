@@ -18,6 +18,9 @@ $(PROJ).fasm: $(PROJ).json $(PDC)
 $(PROJ).bit: $(PROJ).fasm
 	$(PRJOXIDE) pack $(PROJ).fasm $(PROJ).bit
 
+$(PROJ).svf: $(PROJ).fasm
+	$(PRJOXIDE) pack --svf $(PROJ).svf $(PROJ).fasm $(PROJ).bit
+
 prog: $(PROJ).bit
 	$(ECPPROG) -S $(PROJ).bit
 
@@ -25,7 +28,7 @@ prog-flash: $(PROJ).bit
 	$(ECPPROG) $(PROJ).bit
 
 clean:
-	rm -f $(PROJ).json $(PROJ).fasm $(PROJ)_syn.log $(PROJ).bit
+	rm -f $(PROJ).json $(PROJ).fasm $(PROJ)_syn.log $(PROJ).bit $(PROJ).svf
 
 .SECONDARY:
 .PHONY: prog prog-flash clean
@@ -0,0 +1,21 @@
+from fuzzconfig import FuzzConfig
+import libpyprjoxide
+import fuzzconfig
+
+# In DPRAM mode, the LUTs of SLICEA and SLICEB hold the RAM contents, so their
+# initialisation changes at runtime and must be masked from frame ECC. These are the
+# only slices with a RAM mode that stores data (012-plc-modes only fuzzes DPRAM for A
+# and B; RAMW in SLICEC drives the write port), and PLC is the only tile type with
+# slices. This uses the words and enums found by 010-lut-init and 012-plc-modes, so
+# must run after them.
+cfg = FuzzConfig(job="LUTRAMDYN", device="LIFCL-40", sv="../shared/empty_40.v", tiles=["R2C2:PLC"])
+
+def main():
+    cfg.setup(skip_specimen=True)
+    for slicen in ("A", "B"):
+        for k in range(2):
+            libpyprjoxide.set_dynamic_word(fuzzconfig.db, "LIFCL", "PLC", "SLICE{}.K{}.INIT".format(slicen, k),
+                "SLICE{}.MODE".format(slicen), ["DPRAM"])
+
+if __name__ == "__main__":
+    main()
@@ -121,15 +121,6 @@ impl LocationGrid {
     // Convert a neighbour to a coordinate
     pub fn neighbour_tile(&self, x: usize, y: usize, n: &Neighbour) -> Option<(usize, usize)> {
         match n {
-            Neighbour::RelXY { rel_x, rel_y } => {
-                let nx = (x as i32) + rel_x;
-                let ny = (y as i32) + rel_y;
-                if nx >= 0 && ny >= 0 && (nx as usize) < self.width && (ny as usize) < self.height {
-                    Some((nx as usize, ny as usize))
-                } else {
-                    None
-                }
-            }
             Neighbour::Global => {
                 if x != 0 || y != 0 {
                     Some((0, 0))
@@ -144,23 +135,6 @@ impl LocationGrid {
                     None
                 }
             }
-            Neighbour::Branch => {
-                let branch_col = self.glb.branch_sink_to_origin(x).unwrap();
-                Some((branch_col, y))
-            }
-            Neighbour::BranchDriver { side } => {
-                let offset: i32 = match side {
-                    BranchSide::Right => 2,
-                    BranchSide::Left => -2,
-                };
-                let branch_col = self
-                    .glb
-                    .branch_sink_to_origin((x as i32 + offset) as usize)
-                    .unwrap();
-                Some((branch_col, y))
-            }
-            Neighbour::Spine => return Some(self.glb.spine_sink_to_origin(x, y).unwrap()),
-            Neighbour::HRow => return Some(self.glb.hrow_sink_to_origin(x, y).unwrap()),
             Neighbour::DQSGroup => {
                 let dqs_group = self.col_dqs_group[x];
                 if dqs_group == -1 {
@@ -169,6 +143,7 @@ impl LocationGrid {
                     Some((dqs_group as usize, y))
                 }
             }
+            _ => n.resolve(&self.glb, x, y, self.width, self.height),
         }
     }
     // Make the neighbour array symmetric
@@ -356,7 +331,9 @@ impl LocationGrid {
 
         out.list_begin(&format!("d{}_packages", device_idx))?;
         for package in self.iodb.packages.iter() {
-            out.package_info(package, &Chip::get_package_short_name(package))?;
+            let short_name = Chip::get_package_short_name(package)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            out.package_info(package, &short_name)?;
         }
 
         out.col_dqs_list(&format!("d{}_col_dqs_group", device_idx), &self.col_dqs_group)?;
@@ -78,6 +78,42 @@ impl Neighbour {
             None => (None, s),
         }
     }
+    // Location of the tile a neighbour refers to, seen from the tile at (x, y) in a grid
+    // of width by height tiles. Locations off the edge of the grid, branches and spines
+    // with no origin, and neighbours with no single location (global, VCC and DQS group
+    // wires) are None.
+    pub fn resolve(
+        &self,
+        glb: &DeviceGlobalsData,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Option<(usize, usize)> {
+        let offset = |rel_x: i32, rel_y: i32| {
+            let nx = (x as i32) + rel_x;
+            let ny = (y as i32) + rel_y;
+            if nx >= 0 && ny >= 0 && (nx as usize) < width && (ny as usize) < height {
+                Some((nx as usize, ny as usize))
+            } else {
+                None
+            }
+        };
+        match self {
+            Neighbour::RelXY { rel_x, rel_y } => offset(*rel_x, *rel_y),
+            Neighbour::Branch => glb.branch_sink_to_origin(x).map(|c| (c, y)),
+            Neighbour::BranchDriver { side } => {
+                let (col, _) = match side {
+                    BranchSide::Right => offset(2, 0),
+                    BranchSide::Left => offset(-2, 0),
+                }?;
+                glb.branch_sink_to_origin(col).map(|c| (c, y))
+            }
+            Neighbour::Spine => glb.spine_sink_to_origin(x, y),
+            Neighbour::HRow => glb.hrow_sink_to_origin(x, y),
+            Neighbour::Global | Neighbour::DQSGroup | Neighbour::Vcc => None,
+        }
+    }
 }
 
 pub struct NeighbourWire {
@@ -216,3 +252,53 @@ impl TileTypes {
         self.types.get(tt)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 10x10 device with one branch, driven from column 1, covering columns 0-3
+    fn test_globals() -> DeviceGlobalsData {
+        DeviceGlobalsData {
+            branches: vec![GlobalBranchData {
+                branch_col: 1,
+                from_col: 0,
+                tap_driver_col: 1,
+                tap_side: "L".to_string(),
+                to_col: 3,
+            }],
+            spines: vec![GlobalSpineData { from_row: 0, spine_row: 4, to_row: 9 }],
+            hrows: vec![GlobalHrowData { hrow_col: 5, spine_cols: vec![2, 8] }],
+        }
+    }
+
+    fn resolve(wire: &str, x: usize, y: usize) -> Option<(usize, usize)> {
+        let (neigh, _) = Neighbour::parse_wire(wire);
+        neigh.unwrap().resolve(&test_globals(), x, y, 10, 10)
+    }
+
+    #[test]
+    fn resolve_in_device() {
+        assert_eq!(resolve("N1E2:H00", 4, 4), Some((6, 3)));
+        assert_eq!(resolve("BRANCH:HPBX0000", 3, 7), Some((1, 7)));
+        assert_eq!(resolve("BRANCH_R:HPBX0000", 0, 7), Some((1, 7)));
+        assert_eq!(resolve("SPINE:VPSX0000", 1, 7), Some((2, 4)));
+        assert_eq!(resolve("HROW:HPRX0000", 7, 6), Some((5, 4)));
+        assert_eq!(resolve("G:HPBX0000", 4, 4), None);
+    }
+
+    #[test]
+    fn resolve_at_edge() {
+        // Off the top left corner
+        assert_eq!(resolve("W1:H00", 0, 4), None);
+        assert_eq!(resolve("N1:V00", 4, 0), None);
+        assert_eq!(resolve("BRANCH_L:HPBX0000", 1, 4), None);
+        // Off the bottom right corner
+        assert_eq!(resolve("E1:H00", 9, 4), None);
+        assert_eq!(resolve("S2:V00", 4, 8), None);
+        // The last row and column are in the device
+        assert_eq!(resolve("S1E1:H00", 8, 8), Some((9, 9)));
+        // In the device, but with no branch there
+        assert_eq!(resolve("BRANCH:HPBX0000", 9, 4), None);
+    }
+}
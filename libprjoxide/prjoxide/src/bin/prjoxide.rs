@@ -9,10 +9,19 @@ use prjoxide::bba::timing::*;
 
 use prjoxide::bitstream::*;
 use prjoxide::chip::*;
+use prjoxide::clocks::*;
 use prjoxide::database::*;
+use prjoxide::diff::*;
 use prjoxide::fasmparse::*;
-
-use std::convert::TryInto;
+use prjoxide::flash::*;
+use prjoxide::image::*;
+use prjoxide::ioreport::*;
+use prjoxide::mem::*;
+use prjoxide::pinout::*;
+use prjoxide::routing::*;
+use prjoxide::utilisation::*;
+
+use std::convert::{TryFrom, TryInto};
 use std::fs::File;
 use std::io::*;
 
@@ -33,6 +42,28 @@ enum SubCommand {
     Pack(Pack),
     /// unpack a bitstream into FASM.
     Unpack(Unpack),
+    /// disassemble the commands in a bitstream.
+    Bitinfo(Bitinfo),
+    /// extract EBR and LRAM initialisation from a bitstream into $readmemh files.
+    ExtractMem(ExtractMem),
+    /// replace EBR or LRAM contents in an existing bitstream.
+    PatchMem(PatchMem),
+    /// recover the routed nets in a bitstream as JSON.
+    Nets(Nets),
+    /// compare two bitstreams or FASM files at feature level.
+    Diff(Diff),
+    /// check that a FASM file survives packing and unpacking unchanged.
+    Verify(Verify),
+    /// list the IO configuration of every pin of a package.
+    IoReport(IoReport),
+    /// export the IO pinout of a device package as CSV, JSON or a KiCad symbol.
+    Pinout(Pinout),
+    /// count the used logic, memory, DSP, clock and IO resources, per device and per region.
+    Utilisation(Utilisation),
+    /// report which clocks use the global clock network, and any badly configured clock paths.
+    Clocks(Clocks),
+    /// build a multi-image SPI flash image from golden and primary bitstreams (jump table not yet checked on hardware).
+    Mkflash(Mkflash),
     /// export a BBA file for the nextpnr build.
     BBAExport(BBAExport),
     #[cfg(feature = "interchange")]
@@ -40,6 +71,50 @@ enum SubCommand {
     InterchangeExport(InterchangeExport),
 }
 
+// Parse an integer command line option, exiting with an error if invalid
+fn parse_int_option(name: &str, value: &str) -> u64 {
+    parse_attr_int(value).unwrap_or_else(|| {
+        eprintln!("invalid value '{}' for --{}", value, name);
+        std::process::exit(1);
+    })
+}
+
+// Parse an integer option that must fit in a narrower type, exiting if it doesn't
+fn parse_sized_option<T: TryFrom<u64>>(name: &str, value: &str) -> T {
+    let x = parse_int_option(name, value);
+    T::try_from(x).unwrap_or_else(|_| {
+        eprintln!("value 0x{:X} out of range for --{}", x, name);
+        std::process::exit(1);
+    })
+}
+
+// Build a chip from FASM, exiting with the first error (or all of them, if keep_going is set)
+fn chip_from_fasm(db: &mut Database, fasm: &ParsedFasm, device: Option<&str>, keep_going: bool) -> Chip {
+    Chip::from_fasm(db, fasm, device, keep_going).unwrap_or_else(|errors| {
+        for e in errors.iter() {
+            eprintln!("error: {}", e);
+        }
+        if !keep_going {
+            eprintln!("stopped at the first error, use --keep-going to report them all");
+        }
+        eprintln!("Please make sure Oxide and nextpnr are up to date and input source code is meaningful. If they are, consider reporting this as an issue.");
+        std::process::exit(1);
+    })
+}
+
+// Load a chip from a FASM file (by extension) or a bitstream
+fn load_chip(db: &mut Database, filename: &str) -> Chip {
+    if filename.ends_with(".fasm") {
+        let parsed_fasm = ParsedFasm::parse(filename).unwrap();
+        chip_from_fasm(db, &parsed_fasm, None, false)
+    } else {
+        BitstreamParser::parse_file(db, filename).unwrap_or_else(|e| {
+            eprintln!("failed to parse bitstream {}: {}", filename, e);
+            std::process::exit(1);
+        })
+    }
+}
+
 #[derive(Parser)]
 struct Pack {
     /// create background programmable bitstream (advanced).
@@ -51,6 +126,45 @@ struct Pack {
     /// compress bitstream
     #[clap(long)]
     compress: bool,
+    /// add a SED CRC (experimental: the CRC algorithm is not confirmed against hardware).
+    #[clap(long = "experimental-sed-crc", hide = true)]
+    sed_crc: bool,
+    /// lock the device against configuration readback (encoding not yet checked against Radiant).
+    #[clap(long)]
+    secure: bool,
+    /// set the USERCODE register (overrides oxide.usercode in the FASM).
+    #[clap(long)]
+    usercode: Option<String>,
+    /// set the CTRL0 register (overrides oxide.ctrl0 in the FASM).
+    #[clap(long)]
+    ctrl0: Option<String>,
+    /// set the power control register (overrides oxide.power_ctrl in the FASM).
+    #[clap(long)]
+    power_ctrl: Option<String>,
+    /// write a partial bitstream with only the frames that differ from this FASM file.
+    #[clap(long)]
+    partial_base: Option<String>,
+    /// also write an SVF file for programming SRAM over JTAG (not yet tested on hardware; full bitstreams only).
+    #[clap(long)]
+    svf: Option<String>,
+    /// output format: raw, ihex or srec.
+    #[clap(long, default_value = "raw")]
+    format: ImageFormat,
+    /// flash base address for ihex and srec output.
+    #[clap(long, default_value = "0")]
+    base_addr: String,
+    /// reverse the bits in each byte of the output.
+    #[clap(long)]
+    bit_reverse: bool,
+    /// report every FASM error rather than stopping at the first.
+    #[clap(long)]
+    keep_going: bool,
+    /// treat FASM features that set the same bit to different values as errors.
+    #[clap(long)]
+    strict_conflicts: bool,
+    /// number of threads used to encode frames, 0 for one per CPU.
+    #[clap(long, default_value = "1")]
+    threads: usize,
     /// input FASM file.
     fasm: String,
     /// output bitstream.
@@ -60,10 +174,23 @@ struct Pack {
 
 impl Pack {
     pub fn run(&self) -> Result<()> {
+        if self.svf.is_some() && self.partial_base.is_some() {
+            // The SVF erases the SRAM first, so would leave only the changed frames loaded
+            eprintln!("--svf can't be used with --partial-base");
+            std::process::exit(1);
+        }
         let mut db = Database::new_builtin(DATABASE_DIR);
-        let parsed_fasm = ParsedFasm::parse(&self.fasm).unwrap();
+        let parsed_fasm = ParsedFasm::parse(&self.fasm)?;
 
-        let mut chip = Chip::from_fasm(&mut db, &parsed_fasm, None);
+        let mut chip = chip_from_fasm(&mut db, &parsed_fasm, None, self.keep_going);
+        let conflicts = chip.bit_conflicts();
+        let kind = if self.strict_conflicts { "error" } else { "warning" };
+        for c in conflicts.iter() {
+            eprintln!("{}: {}", kind, c);
+        }
+        if self.strict_conflicts && !conflicts.is_empty() {
+            std::process::exit(1);
+        }
 
         if self.background {
             chip.settings.insert("background".to_string(), "1".to_string());
@@ -74,16 +201,52 @@ impl Pack {
         if self.compress {
             chip.settings.insert("compress".to_string(), "1".to_string());
         }
+        if self.sed_crc {
+            chip.settings.insert("sed_crc".to_string(), "1".to_string());
+        }
+        if self.secure {
+            chip.settings.insert("secure".to_string(), "1".to_string());
+        }
+        if let Some(usercode) = &self.usercode {
+            chip.usercode = parse_sized_option("usercode", usercode);
+        }
+        if let Some(ctrl0) = &self.ctrl0 {
+            chip.ctrl0 = parse_sized_option("ctrl0", ctrl0);
+        }
+        if let Some(power_ctrl) = &self.power_ctrl {
+            chip.power_ctrl = parse_sized_option("power-ctrl", power_ctrl);
+        }
 
-        let bs = BitstreamParser::serialise_chip(&chip);
-        let mut outfile = File::create(&self.bitstream).unwrap();
-        outfile.write_all(&bs)?;
+        let bs = match &self.partial_base {
+            Some(base_fasm) => {
+                let parsed_base = ParsedFasm::parse(base_fasm)?;
+                let base_chip = chip_from_fasm(&mut db, &parsed_base, Some(&chip.device), self.keep_going);
+                BitstreamParser::serialise_partial(&base_chip, &chip, self.threads)
+            }
+            None => BitstreamParser::serialise_chip_threads(&chip, self.threads),
+        }
+        .unwrap_or_else(|e| {
+            eprintln!("failed to write bitstream: {}", e);
+            std::process::exit(1);
+        });
+        let base_addr: u32 = parse_sized_option("base-addr", &self.base_addr);
+        let mut outfile = BufWriter::new(File::create(&self.bitstream).unwrap());
+        write_image(&mut outfile, &bs, self.format, base_addr, self.bit_reverse)?;
+        outfile.flush()?;
+        if let Some(svf_file) = &self.svf {
+            let mut svf_out = BufWriter::new(File::create(svf_file)?);
+            prjoxide::svf::write_svf(&mut svf_out, chip.get_idcode(), &bs)?;
+            svf_out.flush()?;
+        }
         Ok(())
     }
 }
 
 #[derive(Parser)]
 struct Unpack {
+    /// number of threads used to decode frames, 0 for one per CPU.
+    #[clap(long, default_value = "1")]
+    threads: usize,
     /// input bitstream.
     bitstream: String,
     /// output FASM file.
@@ -93,12 +256,24 @@ struct Unpack {
 impl Unpack {
     pub fn run(&self) -> Result<()> {
         let mut db = Database::new_builtin(DATABASE_DIR);
-        let chip = BitstreamParser::parse_file(&mut db, &self.bitstream).unwrap();
+        let chip = BitstreamParser::parse_file_threads(&mut db, &self.bitstream, self.threads).unwrap_or_else(|e| {
+            eprintln!("failed to parse bitstream: {}", e);
+            std::process::exit(1);
+        });
 
         let mut outfile = File::create(&self.fasm)?;
 
         writeln!(outfile, "{{ oxide.device=\"{}\" }}", chip.device)?;
         writeln!(outfile, "{{ oxide.device_variant=\"{}\" }}", chip.variant)?;
+        writeln!(outfile, "{{ oxide.usercode=\"0x{:08X}\" }}", chip.usercode)?;
+        writeln!(outfile, "{{ oxide.ctrl0=\"0x{:08X}\" }}", chip.ctrl0)?;
+        writeln!(outfile, "{{ oxide.power_ctrl=\"0x{:02X}\" }}", chip.power_ctrl)?;
+        if let Some(value) = chip.settings.get("secure") {
+            writeln!(outfile, "{{ oxide.secure=\"{}\" }}", value)?;
+        }
+        if chip.settings.contains_key("sed_crc") {
+            writeln!(outfile, "# SED CRC present, pack with --experimental-sed-crc to keep it")?;
+        }
         writeln!(outfile, "")?;
 
         for metadata in chip.metadata.iter() {
@@ -108,18 +283,418 @@ impl Unpack {
             writeln!(outfile, "")?;
         }
 
-        for tile in chip.tiles {
-            tile.write_fasm(&mut db, &mut outfile);
+        let mut warnings = Vec::new();
+        for tile in chip.tiles.iter() {
+            warnings.extend(tile.write_fasm(&mut db, &mut outfile));
         }
 
-        for (addr, val) in chip.ipconfig.iter() {
-            writeln!(outfile, "IP_UNKNOWN.0x{:08X}[7:0] = 8'h{:02X};", addr, val)?;
+        warnings.extend(chip.write_ip_fasm(&mut db, &mut outfile));
+        for w in warnings.iter() {
+            eprintln!("warning: {}", w);
         }
 
         Ok(())
     }
 }
 
+#[derive(Parser)]
+struct Bitinfo {
+    /// print commands as JSON rather than text.
+    #[clap(long)]
+    json: bool,
+    /// input bitstream.
+    bitstream: String,
+}
+
+impl Bitinfo {
+    pub fn run(&self) -> Result<()> {
+        let mut db = Database::new_builtin(DATABASE_DIR);
+        let mut buffer = Vec::new();
+        File::open(&self.bitstream)?.read_to_end(&mut buffer)?;
+        let mut parser = BitstreamParser::new(&buffer);
+        // Print whatever was parsed, even if parsing failed part way through
+        let result = parser.parse(&mut db);
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(parser.commands()).unwrap());
+        } else {
+            for cmd in parser.commands() {
+                let params = cmd.params.iter().map(|x| format!("{:02X}", x)).collect::<Vec<_>>().join(" ");
+                let crc = match cmd.crc_ok {
+                    Some(true) => "ok",
+                    Some(false) => "FAIL",
+                    None => "-",
+                };
+                println!("0x{:08X}: {:<22} {:<8} len={:<8} crc={:<4} {}",
+                    cmd.offset, cmd.name, params, cmd.payload_len, crc, cmd.info);
+            }
+        }
+        if let Err(e) = result {
+            eprintln!("parse error: {}", e);
+            std::process::exit(1);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+struct ExtractMem {
+    /// input bitstream.
+    bitstream: String,
+    /// output directory; one <memory>.mem file is written per initialised memory.
+    outdir: String,
+}
+
+impl ExtractMem {
+    pub fn run(&self) -> Result<()> {
+        let mut db = Database::new_builtin(DATABASE_DIR);
+        let chip = BitstreamParser::parse_file(&mut db, &self.bitstream).unwrap_or_else(|e| {
+            eprintln!("failed to parse bitstream: {}", e);
+            std::process::exit(1);
+        });
+        std::fs::create_dir_all(&self.outdir)?;
+        for mem in extract_memories(&chip, &mut db).iter() {
+            let filename = format!("{}/{}.mem", self.outdir, mem.name);
+            let mut outfile = BufWriter::new(File::create(&filename)?);
+            mem.write_readmemh(&mut outfile)?;
+            outfile.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+struct Nets {
+    /// input bitstream.
+    bitstream: String,
+    /// output JSON file.
+    json: String,
+}
+
+impl Nets {
+    pub fn run(&self) -> Result<()> {
+        let mut db = Database::new_builtin(DATABASE_DIR);
+        let chip = BitstreamParser::parse_file(&mut db, &self.bitstream).unwrap_or_else(|e| {
+            eprintln!("failed to parse bitstream: {}", e);
+            std::process::exit(1);
+        });
+        let nets = trace_nets(&chip, &mut db);
+        let mut outfile = BufWriter::new(File::create(&self.json)?);
+        serde_json::to_writer_pretty(&mut outfile, &nets)?;
+        outfile.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+struct Diff {
+    /// print differences as JSON rather than text.
+    #[clap(long)]
+    json: bool,
+    /// group enums and words by bel tilegroup, rather than by tile.
+    #[clap(long)]
+    by_bel: bool,
+    /// old bitstream or FASM file.
+    old: String,
+    /// new bitstream or FASM file.
+    new: String,
+}
+
+impl Diff {
+    pub fn run(&self) -> Result<()> {
+        let mut db = Database::new_builtin(DATABASE_DIR);
+        let old = load_chip(&mut db, &self.old);
+        let new = load_chip(&mut db, &self.new);
+        if old.device != new.device {
+            eprintln!("cannot compare {} with {}", old.device, new.device);
+            std::process::exit(1);
+        }
+        let diff = diff_chips(&old, &new, &mut db, self.by_bel);
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&diff).unwrap());
+        } else {
+            write_diff(&mut stdout(), &diff)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+struct Verify {
+    /// print differences as JSON rather than text.
+    #[clap(long)]
+    json: bool,
+    /// report every FASM error rather than stopping at the first.
+    #[clap(long)]
+    keep_going: bool,
+    /// input FASM file.
+    fasm: String,
+}
+
+impl Verify {
+    pub fn run(&self) -> Result<()> {
+        let mut db = Database::new_builtin(DATABASE_DIR);
+        let parsed_fasm = ParsedFasm::parse(&self.fasm)?;
+        let chip = chip_from_fasm(&mut db, &parsed_fasm, None, self.keep_going);
+        let diff = verify_fasm(&parsed_fasm, &chip, &mut db).unwrap_or_else(|e| {
+            eprintln!("failed to parse packed bitstream: {}", e);
+            std::process::exit(1);
+        });
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&diff).unwrap());
+        } else {
+            write_diff(&mut stdout(), &diff)?;
+        }
+        let all = || diff.values().flatten();
+        let is_unknown = |d: &&FeatureDiff| d.feature.starts_with("UNKNOWN.");
+        let unknown = all().filter(|d| d.old.is_none() && is_unknown(d)).count();
+        let dropped = all().filter(|d| d.new.is_none()).count();
+        let added = all().filter(|d| d.old.is_none() && !is_unknown(d)).count();
+        let altered = all().filter(|d| d.old.is_some() && d.new.is_some()).count();
+        if diff.is_empty() {
+            eprintln!("{}: round trip OK", self.fasm);
+        } else {
+            eprintln!(
+                "{}: {} dropped, {} added, {} altered features, {} UNKNOWN bits",
+                self.fasm, dropped, added, altered, unknown
+            );
+            std::process::exit(1);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+struct IoReport {
+    /// package name, e.g. CABGA400 or BG400.
+    #[clap(long)]
+    package: String,
+    /// print the report as JSON rather than a table.
+    #[clap(long)]
+    json: bool,
+    /// input bitstream or FASM file.
+    bitstream: String,
+}
+
+impl IoReport {
+    pub fn run(&self) -> Result<()> {
+        let mut db = Database::new_builtin(DATABASE_DIR);
+        let chip = load_chip(&mut db, &self.bitstream);
+        let pins = io_report(&chip, &mut db, &self.package).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&pins).unwrap());
+        } else {
+            write_io_report(&mut stdout(), &pins)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+struct Pinout {
+    /// device name, e.g. LIFCL-40.
+    #[clap(long)]
+    device: String,
+    /// package name, e.g. QFN72 or SG72.
+    #[clap(long)]
+    package: String,
+    /// output format: csv, json or kicad.
+    #[clap(long, default_value = "csv")]
+    format: PinoutFormat,
+    /// output file, standard output if not given.
+    output: Option<String>,
+}
+
+impl Pinout {
+    pub fn run(&self) -> Result<()> {
+        let mut db = Database::new_builtin(DATABASE_DIR);
+        let (family, device, _data) = db.device_by_name(&self.device).unwrap_or_else(|| {
+            eprintln!("no device in database with name {}", self.device);
+            std::process::exit(1);
+        });
+        let iodb = db.device_iodb(&family, &device);
+        let pkg_idx = iodb.package_index(&self.package).unwrap_or_else(|| {
+            eprintln!(
+                "no package {} for {}, available packages: {}",
+                self.package,
+                device,
+                iodb.packages.join(", ")
+            );
+            std::process::exit(1);
+        });
+        let pins = package_pinout(iodb, pkg_idx);
+        let name = format!("{}-{}", device, iodb.packages[pkg_idx]);
+        match &self.output {
+            Some(filename) => {
+                let mut outfile = BufWriter::new(File::create(filename)?);
+                write_pinout(&mut outfile, self.format, &name, &pins)?;
+                outfile.flush()
+            }
+            None => write_pinout(&mut stdout(), self.format, &name, &pins),
+        }
+    }
+}
+
+#[derive(Parser)]
+struct Utilisation {
+    /// print the report as JSON rather than text.
+    #[clap(long)]
+    json: bool,
+    /// input bitstream or FASM file.
+    bitstream: String,
+}
+
+impl Utilisation {
+    pub fn run(&self) -> Result<()> {
+        let mut db = Database::new_builtin(DATABASE_DIR);
+        let chip = load_chip(&mut db, &self.bitstream);
+        let util = utilisation(&chip, &mut db).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&util).unwrap());
+        } else {
+            write_utilisation(&mut stdout(), &chip.device, &util)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+struct Clocks {
+    /// print the report as JSON rather than text.
+    #[clap(long)]
+    json: bool,
+    /// input bitstream or FASM file.
+    bitstream: String,
+}
+
+impl Clocks {
+    pub fn run(&self) -> Result<()> {
+        let mut db = Database::new_builtin(DATABASE_DIR);
+        let chip = load_chip(&mut db, &self.bitstream);
+        let report = analyse_clocks(&chip, &mut db);
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        } else {
+            write_clock_report(&mut stdout(), &report)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+struct PatchMem {
+    /// name of the memory to replace, e.g. EBR_WID3 or LRAM_0.
+    #[clap(long, conflicts_with = "from", required_unless_present = "from")]
+    name: Option<String>,
+    /// find the memory to replace by its current contents, given as a $readmemh file.
+    #[clap(long)]
+    from: Option<String>,
+    /// new contents, as a $readmemh file of 40-bit words.
+    to: String,
+    /// input bitstream.
+    input: String,
+    /// output bitstream.
+    output: String,
+}
+
+impl PatchMem {
+    pub fn run(&self) -> Result<()> {
+        let read_words = |filename: &str, max_words: usize| -> Result<Vec<u64>> {
+            let text = std::fs::read_to_string(filename)?;
+            Ok(parse_readmemh(&text, max_words).unwrap_or_else(|e| {
+                eprintln!("failed to parse {}: {}", filename, e);
+                std::process::exit(1);
+            }))
+        };
+        let exit_on_err = |e: String| -> ! {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        };
+        let mut db = Database::new_builtin(DATABASE_DIR);
+        let mut chip = BitstreamParser::parse_file(&mut db, &self.input).unwrap_or_else(|e| {
+            eprintln!("failed to parse bitstream: {}", e);
+            std::process::exit(1);
+        });
+        let name = match (&self.name, &self.from) {
+            (Some(name), _) => name.to_string(),
+            (None, Some(from)) => {
+                // Contents can only match a memory at least as large
+                let max_words = extract_memories(&chip, &mut db).iter().map(|m| m.words.len()).max().unwrap_or(0);
+                find_memory_by_contents(&chip, &mut db, &read_words(from, max_words)?)
+                    .unwrap_or_else(|e| exit_on_err(e))
+            }
+            (None, None) => unreachable!(),
+        };
+        let max_words = memory_words(&chip, &mut db, &name).unwrap_or_else(|e| exit_on_err(e));
+        write_memory(&mut chip, &mut db, &name, &read_words(&self.to, max_words)?)
+            .unwrap_or_else(|e| exit_on_err(e));
+        let bs = BitstreamParser::serialise_chip(&chip).unwrap_or_else(|e| exit_on_err(e.to_string()));
+        let mut outfile = File::create(&self.output)?;
+        outfile.write_all(&bs)?;
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+struct Mkflash {
+    /// flash offset of the golden image.
+    #[clap(long, default_value = "0x10000")]
+    golden_offset: String,
+    /// primary bitstream and flash offset, as file.bit@0xOFFSET; the first is jumped to at boot.
+    #[clap(long, required = true)]
+    primary: Vec<String>,
+    /// flash sector size that all images must be aligned to.
+    #[clap(long, default_value = "0x10000")]
+    align: String,
+    /// SPI read opcode to switch to before jumping (e.g. 0x0B for fast read).
+    #[clap(long, default_value = "0x03")]
+    spi_mode: String,
+    /// golden bitstream.
+    golden: String,
+    /// output flash image.
+    flash: String,
+}
+
+impl Mkflash {
+    pub fn run(&self) -> Result<()> {
+        let read_image = |name: &str, filename: &str, offset: u32| -> Result<FlashImage> {
+            let mut data = Vec::new();
+            File::open(filename)?.read_to_end(&mut data)?;
+            Ok(FlashImage::new(name, offset, data))
+        };
+        let golden_offset = parse_sized_option("golden-offset", &self.golden_offset);
+        let golden = read_image("golden image", &self.golden, golden_offset)?;
+        let mut primaries = Vec::new();
+        for (i, primary) in self.primary.iter().enumerate() {
+            let (filename, offset) = match primary.rsplit_once('@') {
+                Some((f, o)) => (f, parse_sized_option("primary", o)),
+                None => {
+                    eprintln!("--primary '{}' must be of the form file.bit@offset", primary);
+                    std::process::exit(1);
+                }
+            };
+            primaries.push(read_image(&format!("primary image {}", i), filename, offset)?);
+        }
+        let align = parse_sized_option("align", &self.align);
+        let spi_mode = parse_sized_option("spi-mode", &self.spi_mode);
+        let mut db = Database::new_builtin(DATABASE_DIR);
+        let flash = check_images(&mut db, &golden, &primaries)
+            .and_then(|_| build_flash(&golden, &primaries, align, spi_mode))
+            .unwrap_or_else(|e| {
+                eprintln!("failed to build flash image: {}", e);
+                std::process::exit(1);
+            });
+        let mut outfile = File::create(&self.flash)?;
+        outfile.write_all(&flash)?;
+        Ok(())
+    }
+}
+
 #[derive(Parser)]
 struct BBAExport {
     /// device family name.
@@ -226,6 +801,39 @@ fn main() -> Result<()> {
         SubCommand::Unpack(t) => {
             t.run()
         }
+        SubCommand::Bitinfo(t) => {
+            t.run()
+        }
+        SubCommand::ExtractMem(t) => {
+            t.run()
+        }
+        SubCommand::PatchMem(t) => {
+            t.run()
+        }
+        SubCommand::Nets(t) => {
+            t.run()
+        }
+        SubCommand::Diff(t) => {
+            t.run()
+        }
+        SubCommand::Verify(t) => {
+            t.run()
+        }
+        SubCommand::IoReport(t) => {
+            t.run()
+        }
+        SubCommand::Pinout(t) => {
+            t.run()
+        }
+        SubCommand::Utilisation(t) => {
+            t.run()
+        }
+        SubCommand::Clocks(t) => {
+            t.run()
+        }
+        SubCommand::Mkflash(t) => {
+            t.run()
+        }
         SubCommand::BBAExport(t) => {
             t.run()
         }
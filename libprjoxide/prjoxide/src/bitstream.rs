@@ -1,18 +1,120 @@
 use crate::chip::*;
 use crate::database::*;
 
+use std::collections::BTreeSet;
 use std::convert::TryInto;
+use std::fmt;
 use std::fs::File;
 use std::io::Read;
 
+use log::*;
+use serde::Serialize;
+
 pub struct BitstreamParser {
     data: Vec<u8>,
     index: usize,
     crc16: u16,
-    ecc14: u16,
-    verbose: bool,
+    sed_crc: u32,
+    // None if there is no SED CRC, otherwise whether it matched the frame data
+    sed_crc_ok: Option<bool>,
     metadata: Vec<String>,
     comp_dic: [u8; 16],
+    commands: Vec<BitstreamCommand>,
+    cmd_crc: Option<bool>,
+    // (address, index, ECC) of each frame parsed
+    frame_ecc: Vec<(u32, usize, u16)>,
+    // Number of threads used to encode and decode frames
+    threads: usize,
+}
+
+// Errors that can occur while parsing a bitstream
+#[derive(Debug, Clone, PartialEq)]
+pub enum BitstreamError {
+    Io(String),
+    NoPreamble,
+    UnexpectedEnd { offset: usize },
+    UnknownCommand { offset: usize, opcode: u8 },
+    UnknownIdcode { offset: usize, idcode: u32 },
+    MissingIdcode { offset: usize },
+    BadByte { offset: usize, expected: u8, actual: u8 },
+    BadPadding { offset: usize, frame: usize, bit: usize },
+    CrcMismatch { offset: usize, expected: u16, actual: u16 },
+    BadFrameAddress { offset: usize, addr: u32 },
+    BadBusAddress { offset: usize, addr: u32 },
+    // Errors writing a bitstream, for configuration the device can't hold
+    NoSuchFrame { addr: u32 },
+    NoSuchBusAddress { addr: u32 },
+    DeviceMismatch { base: String, design: String },
+}
+
+impl fmt::Display for BitstreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BitstreamError::Io(e) => write!(f, "failed to read bitstream: {}", e),
+            BitstreamError::NoPreamble => write!(f, "failed to find preamble"),
+            BitstreamError::UnexpectedEnd { offset } => {
+                write!(f, "unexpected end of bitstream at {}", offset)
+            }
+            BitstreamError::UnknownCommand { offset, opcode } => {
+                write!(f, "unknown command 0x{:02X} at {}", opcode, offset)
+            }
+            BitstreamError::UnknownIdcode { offset, idcode } => write!(
+                f,
+                "no device in database with IDCODE 0x{:08X} (at {})",
+                idcode, offset
+            ),
+            BitstreamError::MissingIdcode { offset } => {
+                write!(f, "got bitstream content before IDCODE at {}", offset)
+            }
+            BitstreamError::BadByte { offset, expected, actual } => write!(
+                f,
+                "expected 0x{:02X} at {}, got 0x{:02X}",
+                expected, offset, actual
+            ),
+            BitstreamError::BadPadding { offset, frame, bit } => write!(
+                f,
+                "padding bit {} of readback frame {} at {} is not set",
+                bit, frame, offset
+            ),
+            BitstreamError::CrcMismatch { offset, expected, actual } => write!(
+                f,
+                "CRC mismatch at {}: bitstream has 0x{:04X}, calculated 0x{:04X}",
+                offset, expected, actual
+            ),
+            BitstreamError::BadFrameAddress { offset, addr } => {
+                write!(f, "invalid frame address 0x{:08X} at {}", addr, offset)
+            }
+            BitstreamError::BadBusAddress { offset, addr } => {
+                write!(f, "invalid bus address 0x{:08X} at {}", addr, offset)
+            }
+            BitstreamError::NoSuchFrame { addr } => {
+                write!(f, "frame address 0x{:08X} is not in the device", addr)
+            }
+            BitstreamError::NoSuchBusAddress { addr } => {
+                write!(f, "IP configuration address 0x{:08X} is not on the configuration bus", addr)
+            }
+            BitstreamError::DeviceMismatch { base, design } => write!(
+                f,
+                "partial bitstream base is for {}, but the design is for {}",
+                base, design
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BitstreamError {}
+
+// A single command, as found by the parser
+#[derive(Serialize, Clone)]
+pub struct BitstreamCommand {
+    pub offset: usize,
+    pub opcode: u8,
+    pub name: String,
+    pub params: Vec<u8>,
+    pub payload_len: usize,
+    // None if the command carries no CRC, otherwise whether every CRC in it matched
+    pub crc_ok: Option<bool>,
+    pub info: String,
 }
 
 // Magic sequences
@@ -23,10 +125,7 @@ const PREAMBLE: [u8; 4] = [0xFF, 0xFF, 0xBD, 0xB3];
 
 // Commands
 
-#[allow(dead_code)]
 const SPI_MODE: u8 = 0b01111001;
-
-#[allow(dead_code)]
 const JUMP: u8 = 0b01111110;
 
 const LSC_RESET_CRC: u8 = 0b00111011;
@@ -42,10 +141,8 @@ const LSC_WRITE_ADDRESS: u8 = 0b10110100;
 const LSC_PROG_INCR_CMP: u8 = 0b10111000;
 const LSC_PROG_INCR_RTI: u8 = 0b10000010;
 
-#[allow(dead_code)]
 const LSC_PROG_SED_CRC: u8 = 0b10100010;
 
-#[allow(dead_code)]
 const ISC_PROGRAM_SECURITY: u8 = 0b11001110;
 const ISC_PROGRAM_USERCODE: u8 = 0b11000010;
 const LSC_BUS_ADDRESS: u8 = 0b11110110;
@@ -66,6 +163,159 @@ const CRC16_INIT: u16 = 0x0000;
 const ECC_POLY: u16 = 0x202D;
 const ECC_INIT: u16 = 0x0000;
 
+// SED CRC32 constants (CRC-32/MPEG-2: MSB first, no final XOR)
+const SED_CRC_POLY: u32 = 0x04C11DB7;
+const SED_CRC_INIT: u32 = 0xFFFFFFFF;
+
+pub fn command_name(opcode: u8) -> &'static str {
+    match opcode {
+        SPI_MODE => "SPI_MODE",
+        JUMP => "JUMP",
+        LSC_RESET_CRC => "LSC_RESET_CRC",
+        VERIFY_ID => "VERIFY_ID",
+        LSC_WRITE_COMP_DIC => "LSC_WRITE_COMP_DIC",
+        LSC_PROG_CNTRL0 => "LSC_PROG_CNTRL0",
+        LSC_INIT_ADDRESS => "LSC_INIT_ADDRESS",
+        LSC_WRITE_ADDRESS => "LSC_WRITE_ADDRESS",
+        LSC_PROG_INCR_CMP => "LSC_PROG_INCR_CMP",
+        LSC_PROG_INCR_RTI => "LSC_PROG_INCR_RTI",
+        LSC_PROG_SED_CRC => "LSC_PROG_SED_CRC",
+        ISC_PROGRAM_SECURITY => "ISC_PROGRAM_SECURITY",
+        ISC_PROGRAM_USERCODE => "ISC_PROGRAM_USERCODE",
+        LSC_BUS_ADDRESS => "LSC_BUS_ADDRESS",
+        LSC_BUS_WRITE => "LSC_BUS_WRITE",
+        ISC_PROGRAM_DONE => "ISC_PROGRAM_DONE",
+        LSC_POWER_CTRL => "LSC_POWER_CTRL",
+        DUMMY => "DUMMY",
+        LSC_AUTH_CTRL => "LSC_AUTH_CTRL",
+        _ => "UNKNOWN",
+    }
+}
+
+// Check if a boolean setting, such as "compress", is enabled for a chip
+fn setting_enabled(ch: &Chip, key: &str) -> bool {
+    ch.settings.get(key).map(|v| v == "1").unwrap_or(false)
+}
+
+// Map over items using up to the given number of threads, keeping the results in order
+fn par_map<T: Sync, R: Send>(threads: usize, items: &[T], func: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = threads.min(items.len());
+    if threads <= 1 {
+        return items.iter().map(func).collect();
+    }
+    let func = &func;
+    std::thread::scope(|s| {
+        let handles: Vec<_> = items
+            .chunks(items.len().div_ceil(threads))
+            .map(|chunk| s.spawn(move || chunk.iter().map(func).collect::<Vec<R>>()))
+            .collect();
+        handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+    })
+}
+
+// Add a single *bit* to a frame ECC
+fn update_ecc(ecc: u16, val: bool) -> u16 {
+    let bit_flag = ecc >> 13;
+    let ecc = ((ecc << 1) | (val as u16)) & 0x3FFF;
+    if bit_flag != 0 {
+        ecc ^ ECC_POLY
+    } else {
+        ecc
+    }
+}
+
+// Finalise and return ECC
+fn finalise_ecc(ecc: u16) -> u16 {
+    (0..14).fold(ecc, |ecc, _| update_ecc(ecc, false))
+}
+
+// Compute the 14-bit ECC of a frame, given its bits from high to low
+fn frame_ecc(bits: impl Iterator<Item = bool>) -> u16 {
+    finalise_ecc(bits.fold(ECC_INIT, update_ecc))
+}
+
+// ECC of a frame of the chip, with bits that change at runtime masked out
+fn chip_frame_ecc(c: &Chip, frame_idx: usize) -> u16 {
+    frame_ecc(
+        (0..c.data.bits_per_frame)
+            .rev()
+            .map(|j| c.cram.get(frame_idx, j) && !c.ecc_mask.get(frame_idx, j)),
+    )
+}
+
+// Encode a frame of the chip to len bytes, right aligned, optionally with its ECC
+fn encode_frame(c: &Chip, frame_idx: usize, len: usize, with_ecc: bool) -> Vec<u8> {
+    let pad_bits = c.data.frame_ecc_bits + c.data.pad_bits_after_frame;
+    let mut frame_bytes = vec![0u8; len];
+    let mut ecc = ECC_INIT;
+    for j in (0..c.data.bits_per_frame).rev() {
+        let value = c.cram.get(frame_idx, j);
+        if with_ecc {
+            ecc = update_ecc(ecc, value && !c.ecc_mask.get(frame_idx, j));
+        }
+        if value {
+            let ofs = j + pad_bits;
+            frame_bytes[(len - 1) - (ofs / 8)] |= 1 << (ofs % 8);
+        }
+    }
+    if with_ecc {
+        let ecc = finalise_ecc(ecc);
+        frame_bytes[len - 2] |= ((ecc >> 8) & 0x3F) as u8;
+        frame_bytes[len - 1] |= (ecc & 0xFF) as u8;
+    }
+    frame_bytes
+}
+
+// Decode the set bits of a frame, from high to low
+fn decode_frame(frame_bytes: &[u8], bits_per_frame: usize, pad_bits: usize) -> Vec<usize> {
+    let len = frame_bytes.len();
+    (0..bits_per_frame)
+        .rev()
+        .filter(|&j| {
+            let ofs = j + pad_bits;
+            (frame_bytes[(len - 1) - (ofs / 8)] >> (ofs % 8)) & 0x01 == 0x01
+        })
+        .collect()
+}
+
+// Compress a frame using the dictionary, padding the last byte with zeros
+fn compress_frame(comp_dic: &[u8; 16], frame: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut buffer : u8 = 0;
+    let mut bits_in_buffer : usize = 0;
+    let mut add_bits = |value: u16, len: usize| {
+        for i in (0..len).rev() {
+            if (value & (1 << i)) != 0 {
+                buffer |= 1 << (7 - bits_in_buffer);
+            }
+            bits_in_buffer += 1;
+            if bits_in_buffer == 8 {
+                out.push(buffer);
+                bits_in_buffer = 0;
+                buffer = 0;
+            }
+        }
+    };
+    for b in frame {
+        if *b == 0 {
+            // 0 byte -> 0 bit
+            add_bits(0b0, 1);
+        } else if let Some(i) = comp_dic.iter().position(|d| d == b) {
+            // dictionary entry -> 0b10xxxx
+            add_bits(0b10, 2);
+            add_bits((15 - i) as u16, 4);
+        } else {
+            // literal -> 0b11xxxxxxxx
+            add_bits(0b11, 2);
+            add_bits(*b as u16, 8);
+        }
+    }
+    if bits_in_buffer != 0 {
+        out.push(buffer);
+    }
+    out
+}
+
 enum BitstreamType {
     NORMAL,
     READBACK
@@ -77,128 +327,243 @@ impl BitstreamParser {
             data: bitstream.to_vec(),
             index: 0,
             crc16: CRC16_INIT,
-            ecc14: ECC_INIT,
-            verbose: false,
+            sed_crc: SED_CRC_INIT,
+            sed_crc_ok: None,
             metadata: Vec::new(),
             comp_dic: [0; 16],
+            commands: Vec::new(),
+            cmd_crc: None,
+            frame_ecc: Vec::new(),
+            threads: 1,
         }
     }
 
-    pub fn parse_file(db: &mut Database, filename: &str) -> Result<Chip, &'static str> {
-        let mut f = File::open(filename).map_err(|_x| "failed to open file")?;
+    // Set the number of threads used to encode and decode frames, 0 for one per CPU.
+    // A single thread is used by default, so that parallel runs (such as fuzzing)
+    // aren't oversubscribed
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = match threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+    }
+
+    pub fn parse_file(db: &mut Database, filename: &str) -> Result<Chip, BitstreamError> {
+        Self::parse_file_threads(db, filename, 1)
+    }
+
+    pub fn parse_file_threads(db: &mut Database, filename: &str, threads: usize) -> Result<Chip, BitstreamError> {
+        let mut f = File::open(filename).map_err(|x| BitstreamError::Io(x.to_string()))?;
         let mut buffer = Vec::new();
         // read the whole file
         f.read_to_end(&mut buffer)
-            .map_err(|_x| "failed to read file")?;
+            .map_err(|x| BitstreamError::Io(x.to_string()))?;
         let mut parser = BitstreamParser::new(&buffer);
+        parser.set_threads(threads);
         let mut c = parser.parse(db)?;
         c.cram_to_tiles();
         Ok(c)
     }
 
-    pub fn serialise_chip(ch: &Chip) -> Vec<u8> {
-        let mut b = BitstreamParser {
-            data: Vec::new(),
-            index: 0,
-            crc16: CRC16_INIT,
-            ecc14: ECC_INIT,
-            verbose: false,
-            metadata: Vec::new(),
-            comp_dic: [0; 16],
-        };
-        b.write_string("LSCC"); // magic
-        b.write_bytes(&COMMENT_START); // metadata start
-        for (i, m) in ch.metadata.iter().enumerate() {
-            b.write_string(m);
-            if i < (ch.metadata.len() - 1) {
-                b.write_byte(0x00); // terminator
-            }
-        }
-        b.write_bytes(&COMMENT_END); // metadata end
-        b.write_bytes(&PREAMBLE); // actual bitstream preamble
-        b.write_padding(20);
-        // Reset CRC, twice for some reason
-        b.write_byte(LSC_RESET_CRC);
-        b.write_zeros(3);
-        b.crc16 = CRC16_INIT;
-        b.write_padding(4);
-        b.write_byte(LSC_RESET_CRC);
-        b.write_zeros(3);
-        b.crc16 = CRC16_INIT;
-        b.write_padding(4);
-        // IDCODE check
-        b.write_byte(VERIFY_ID);
-        b.write_zeros(3);
-        b.write_u32(ch.get_idcode());
+    pub fn serialise_chip(ch: &Chip) -> Result<Vec<u8>, BitstreamError> {
+        Self::serialise_chip_threads(ch, 1)
+    }
+
+    pub fn serialise_chip_threads(ch: &Chip, threads: usize) -> Result<Vec<u8>, BitstreamError> {
+        let mut b = BitstreamParser::new(&[]);
+        b.set_threads(threads);
+        b.write_header(ch);
         // Set CTRL0
-        let mut ctrl0 = 0x00000000;
-        let mut compress = false;
-        for (k, v) in ch.settings.iter() {
-            if k == "background" && v == "1" {
-                ctrl0 |= 0x27800000;
-            }
-            if k == "multiboot" && v == "1" {
-                ctrl0 |= 1 << 19;
-            }
-            if k == "compress" && v == "1" {
-                compress = true;
-            }
-        }
+        let ctrl0 = BitstreamParser::get_ctrl0(ch);
+        let compress = setting_enabled(ch, "compress");
+        let sed_crc = setting_enabled(ch, "sed_crc");
+        let secure = setting_enabled(ch, "secure");
         b.write_byte(LSC_PROG_CNTRL0);
         b.write_zeros(3);
         b.write_u32(ctrl0);
         if compress {
-            b.compute_comp_dic(ch);
-            b.write_byte(LSC_WRITE_COMP_DIC);
-            b.write_zeros(3);
-            for i in 0..16 {
-                b.write_byte(b.comp_dic[i]);
-            }
+            b.write_comp_dic(ch);
         }
         // Write "IO" frames
         b.write_frame_addr(0x8000);
         if compress {
-            b.write_comp_frames(ch, 0x8000, 32);
+            b.write_comp_frames(ch, 0x8000, 32)?;
         } else {
-            b.write_frames(ch, 0x8000, 32);
+            b.write_frames(ch, 0x8000, 32)?;
         }
         b.write_padding(17);
         // Write main frames
         b.write_byte(LSC_INIT_ADDRESS);
         b.write_zeros(3);
         if compress {
-            b.write_comp_frames(ch, 0x0000, ch.data.frames - (32 + ch.tap_frame_count));
+            b.write_comp_frames(ch, 0x0000, ch.data.frames - (32 + ch.tap_frame_count))?;
         } else {
-            b.write_frames(ch, 0x0000, ch.data.frames - (32 + ch.tap_frame_count));
+            b.write_frames(ch, 0x0000, ch.data.frames - (32 + ch.tap_frame_count))?;
         }
         b.write_padding(17);
         // Write tap frames
         b.write_frame_addr(0x8020);
         if compress {
-            b.write_comp_frames(ch, 0x8020, ch.tap_frame_count);
+            b.write_comp_frames(ch, 0x8020, ch.tap_frame_count)?;
         } else {
-            b.write_frames(ch, 0x8020, ch.tap_frame_count);
+            b.write_frames(ch, 0x8020, ch.tap_frame_count)?;
         }
         b.write_padding(17);
+        // Write SED CRC over all frames
+        if sed_crc {
+            b.write_byte(LSC_PROG_SED_CRC);
+            b.write_zeros(3);
+            b.write_u32(b.sed_crc);
+            b.write_padding(4);
+        }
         // Write power control
         b.write_byte(LSC_POWER_CTRL);
         b.write_zeros(2);
-        b.write_byte(0x01);
+        b.write_byte(ch.power_ctrl);
         b.write_padding(512);
         // Write IP config
-        b.write_ip_config(ch);
+        b.write_ip_config(ch)?;
         // Write usercode
         b.write_byte(ISC_PROGRAM_USERCODE);
         b.write_byte(0x80); // CRC check enable flag
         b.write_zeros(2);
-        b.write_u32(0x00000000);
+        b.write_u32(ch.usercode);
         b.insert_crc();
         b.write_padding(15);
+        // Disable readback over JTAG/SSPI
+        if secure {
+            b.write_byte(ISC_PROGRAM_SECURITY);
+            b.write_zeros(3);
+            b.write_padding(4);
+        }
         // Program DONE
         b.write_byte(ISC_PROGRAM_DONE);
         b.write_zeros(3);
         b.write_padding(4);
-        return b.data;
+        Ok(b.data)
+    }
+
+    // Create a partial bitstream, containing only the frames that differ
+    // between base and ch, as address-contiguous runs
+    pub fn serialise_partial(base: &Chip, ch: &Chip, threads: usize) -> Result<Vec<u8>, BitstreamError> {
+        if base.device != ch.device {
+            return Err(BitstreamError::DeviceMismatch {
+                base: base.device.to_string(),
+                design: ch.device.to_string(),
+            });
+        }
+        let changed_frames: BTreeSet<usize> =
+            ch.cram.delta(&base.cram).iter().map(|(f, _b, _v)| *f).collect();
+        let mut b = BitstreamParser::new(&[]);
+        b.set_threads(threads);
+        b.write_header(ch);
+        let compress = setting_enabled(ch, "compress");
+        b.write_byte(LSC_PROG_CNTRL0);
+        b.write_zeros(3);
+        b.write_u32(BitstreamParser::get_ctrl0(ch));
+        if compress {
+            b.write_comp_dic(ch);
+        }
+        // Regions in the same order as a full bitstream: IO, main, then TAP frames
+        let regions = [
+            (0x8000, 32),
+            (0x0000, ch.data.frames - (32 + ch.tap_frame_count)),
+            (0x8020, ch.tap_frame_count),
+        ];
+        for (region_start, region_count) in regions.iter() {
+            let mut run_start: Option<u32> = None;
+            for i in 0..=*region_count {
+                let addr = region_start + (i as u32);
+                let changed = i < *region_count
+                    && changed_frames.contains(&Self::frame_index(ch, addr)?);
+                match (changed, run_start) {
+                    (true, None) => run_start = Some(addr),
+                    (false, Some(start)) => {
+                        b.write_frame_addr(start);
+                        if compress {
+                            b.write_comp_frames(ch, start, (addr - start) as usize)?;
+                        } else {
+                            b.write_frames(ch, start, (addr - start) as usize)?;
+                        }
+                        b.write_padding(17);
+                        run_start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        // Program DONE
+        b.write_byte(ISC_PROGRAM_DONE);
+        b.write_zeros(3);
+        b.write_padding(4);
+        Ok(b.data)
+    }
+
+    // Write the container, preamble and IDCODE check common to all bitstreams
+    fn write_header(&mut self, ch: &Chip) {
+        self.write_string("LSCC"); // magic
+        self.write_bytes(&COMMENT_START); // metadata start
+        for (i, m) in ch.metadata.iter().enumerate() {
+            self.write_string(m);
+            if i < (ch.metadata.len() - 1) {
+                self.write_byte(0x00); // terminator
+            }
+        }
+        self.write_bytes(&COMMENT_END); // metadata end
+        self.write_bytes(&PREAMBLE); // actual bitstream preamble
+        self.write_padding(20);
+        // Reset CRC, twice for some reason
+        self.write_byte(LSC_RESET_CRC);
+        self.write_zeros(3);
+        self.crc16 = CRC16_INIT;
+        self.write_padding(4);
+        self.write_byte(LSC_RESET_CRC);
+        self.write_zeros(3);
+        self.crc16 = CRC16_INIT;
+        self.write_padding(4);
+        // IDCODE check
+        self.write_byte(VERIFY_ID);
+        self.write_zeros(3);
+        self.write_u32(ch.get_idcode());
+    }
+
+    // Compute CTRL0, including bits implied by settings
+    fn get_ctrl0(ch: &Chip) -> u32 {
+        let mut ctrl0 = ch.ctrl0;
+        if setting_enabled(ch, "background") {
+            ctrl0 |= 0x27800000;
+        }
+        if setting_enabled(ch, "multiboot") {
+            ctrl0 |= 1 << 19;
+        }
+        ctrl0
+    }
+
+    // Compute and write the compression dictionary
+    fn write_comp_dic(&mut self, ch: &Chip) {
+        self.compute_comp_dic(ch);
+        self.write_byte(LSC_WRITE_COMP_DIC);
+        self.write_zeros(3);
+        for i in 0..16 {
+            self.write_byte(self.comp_dic[i]);
+        }
+    }
+
+    // Create a jump table bitstream, that switches the SPI read mode and
+    // then continues configuration from another flash address
+    pub fn serialise_jump(spi_mode: u8, addr: u32) -> Vec<u8> {
+        let mut b = BitstreamParser::new(&[]);
+        b.write_bytes(&PREAMBLE);
+        b.write_padding(4);
+        b.write_byte(SPI_MODE);
+        b.write_byte(spi_mode);
+        b.write_zeros(2);
+        b.write_padding(4);
+        b.write_byte(JUMP);
+        b.write_zeros(3);
+        b.write_u32(addr);
+        b.write_padding(16);
+        b.data
     }
 
     // Add a single byte to the running CRC16 accumulator
@@ -213,28 +578,38 @@ impl BitstreamParser {
             }
         }
     }
-    // Add a single *bit* to the frame ECC
-    fn update_ecc(&mut self, val: bool) {
-        let bit_flag = self.ecc14 >> 13;
-        self.ecc14 = ((self.ecc14 << 1) | (val as u16)) & 0x3FFF;
-        if bit_flag != 0 {
-            self.ecc14 ^= ECC_POLY;
-        }
-    }
-    // Finalise and return ECC
-    fn finalise_ecc(&mut self) -> u16 {
-        for _i in 0..14 {
-            self.update_ecc(false);
+    // Add a frame worth of bytes to the running SED CRC32
+    fn update_sed_crc(&mut self, frame: &[u8]) {
+        for &val in frame {
+            self.sed_crc ^= (val as u32) << 24;
+            for _ in 0..8 {
+                if self.sed_crc & 0x80000000 != 0 {
+                    self.sed_crc = (self.sed_crc << 1) ^ SED_CRC_POLY;
+                } else {
+                    self.sed_crc <<= 1;
+                }
+            }
         }
-        return self.ecc14;
     }
 
     // Get a single byte, updating the CRC
-    fn get_byte(&mut self) -> u8 {
-        let val = self.data[self.index];
+    fn get_byte(&mut self) -> Result<u8, BitstreamError> {
+        let val = *self
+            .data
+            .get(self.index)
+            .ok_or(BitstreamError::UnexpectedEnd { offset: self.index })?;
         self.index += 1;
         self.update_crc16(val);
-        return val;
+        Ok(val)
+    }
+    // Get a single byte, checking it has the expected value
+    fn expect_byte(&mut self, expected: u8) -> Result<(), BitstreamError> {
+        let offset = self.index;
+        let actual = self.get_byte()?;
+        if actual != expected {
+            return Err(BitstreamError::BadByte { offset, expected, actual });
+        }
+        Ok(())
     }
     // Write a byte into the bitstream, updating the CRC
     fn write_byte(&mut self, b: u8) {
@@ -246,13 +621,16 @@ impl BitstreamParser {
         self.data.push(b);
     }
     // Gets an opcode byte, updating the CRC if it isn't a dummy opcode (0xFF)
-    fn get_opcode_byte(&mut self) -> u8 {
-        let val = self.data[self.index];
+    fn get_opcode_byte(&mut self) -> Result<u8, BitstreamError> {
+        let val = *self
+            .data
+            .get(self.index)
+            .ok_or(BitstreamError::UnexpectedEnd { offset: self.index })?;
         self.index += 1;
         if val != DUMMY {
             self.update_crc16(val);
         }
-        return val;
+        Ok(val)
     }
     // Checks if the stream matches a preamble token, consuming
     // the token and returning true if it does
@@ -268,18 +646,18 @@ impl BitstreamParser {
         }
     }
     // Get a 16-bit big-endian word
-    fn get_u16(&mut self) -> u16 {
-        let mut val = (self.get_byte() as u16) << 8;
-        val |= self.get_byte() as u16;
-        return val;
+    fn get_u16(&mut self) -> Result<u16, BitstreamError> {
+        let mut val = (self.get_byte()? as u16) << 8;
+        val |= self.get_byte()? as u16;
+        Ok(val)
     }
     // Get a 32-bit big-endian word
-    fn get_u32(&mut self) -> u32 {
-        let mut val = (self.get_byte() as u32) << 24;
-        val |= (self.get_byte() as u32) << 16;
-        val |= (self.get_byte() as u32) << 8;
-        val |= self.get_byte() as u32;
-        return val;
+    fn get_u32(&mut self) -> Result<u32, BitstreamError> {
+        let mut val = (self.get_byte()? as u32) << 24;
+        val |= (self.get_byte()? as u32) << 16;
+        val |= (self.get_byte()? as u32) << 8;
+        val |= self.get_byte()? as u32;
+        Ok(val)
     }
     // Write a 16-bit big-endian word
     fn write_u16(&mut self, h: u16) {
@@ -294,16 +672,18 @@ impl BitstreamParser {
         self.write_byte((w & 0xFF) as u8);
     }
     // Copy bytes
-    fn copy_bytes(&mut self, dest: &mut [u8]) {
-        for i in 0..dest.len() {
-            dest[i] = self.get_byte();
+    fn copy_bytes(&mut self, dest: &mut [u8]) -> Result<(), BitstreamError> {
+        for d in dest.iter_mut() {
+            *d = self.get_byte()?;
         }
+        Ok(())
     }
     // Skip bytes
-    fn skip_bytes(&mut self, len: usize) {
+    fn skip_bytes(&mut self, len: usize) -> Result<(), BitstreamError> {
         for _ in 0..len {
-            self.get_byte();
+            self.get_byte()?;
         }
+        Ok(())
     }
     // Write a number of zeroes into the bitstream
     fn write_zeros(&mut self, len: usize) {
@@ -332,64 +712,49 @@ impl BitstreamParser {
         self.write_zeros(3);
         self.write_u32(addr);
     }
-    fn write_frames(&mut self, c: &Chip, start_addr: u32, count: usize) {
+    // Index of the frame at a frame address
+    fn frame_index(c: &Chip, addr: u32) -> Result<usize, BitstreamError> {
+        c.frame_addr_to_idx(addr).ok_or(BitstreamError::NoSuchFrame { addr })
+    }
+    // Indices of count frames from start_addr
+    fn frame_indices(c: &Chip, start_addr: u32, count: usize) -> Result<Vec<usize>, BitstreamError> {
+        (0..count)
+            .map(|f| Self::frame_index(c, start_addr + (f as u32)))
+            .collect()
+    }
+    fn write_frames(&mut self, c: &Chip, start_addr: u32, count: usize) -> Result<(), BitstreamError> {
         self.write_byte(LSC_PROG_INCR_RTI);
         self.write_byte(0x91); // frame load settings
         self.write_u16(count.try_into().unwrap());
-        let bits_per_frame = c.data.bits_per_frame;
-        let pad_bits = c.data.frame_ecc_bits + c.data.pad_bits_after_frame;
-        let mut frame_bytes = vec![0 as u8; (bits_per_frame + 14 + 7) / 8];
-        let total_frame_bytes = frame_bytes.len();
-        for f in 0..count {
-            let frame_addr: u32 = start_addr + (f as u32);
-            let frame_idx = c.frame_addr_to_idx(frame_addr);
-            self.ecc14 = ECC_INIT;
-            for b in frame_bytes.iter_mut() {
-                *b = 0;
-            }
-            for j in (0..bits_per_frame).rev() {
-                let ofs = (j + pad_bits) as usize;
-                let value = c.cram.get(frame_idx, j);
-                self.update_ecc(value);
-                if value {
-                    frame_bytes[(total_frame_bytes - 1) - (ofs / 8)] |= 1 << (ofs % 8);
-                }
-            }
-            let ecc = self.finalise_ecc();
-            frame_bytes[total_frame_bytes - 2] |= ((ecc >> 8) & 0x3F) as u8;
-            frame_bytes[total_frame_bytes - 1] |= (ecc & 0xFF) as u8;
-            self.write_bytes(&frame_bytes);
+        let frame_len = (c.data.bits_per_frame + 14).div_ceil(8);
+        // Frames are encoded in parallel, only the CRCs need to be computed in order
+        let frames = par_map(self.threads, &Self::frame_indices(c, start_addr, count)?, |&idx| {
+            encode_frame(c, idx, frame_len, true)
+        });
+        for frame_bytes in frames.iter() {
+            self.update_sed_crc(frame_bytes);
+            self.write_bytes(frame_bytes);
             self.insert_crc();
             self.write_byte(0xFF);
         }
+        Ok(())
     }
-    fn write_comp_frames(&mut self, c: &Chip, start_addr: u32, count: usize) {
+    fn write_comp_frames(&mut self, c: &Chip, start_addr: u32, count: usize) -> Result<(), BitstreamError> {
         self.write_byte(LSC_PROG_INCR_CMP);
         self.write_byte(0xD4); // frame load settings
         self.write_u16(count.try_into().unwrap());
-        let bits_per_frame = c.data.bits_per_frame;
-        let pad_bits = c.data.frame_ecc_bits + c.data.pad_bits_after_frame;
-        let mut frame_bytes = vec![0 as u8; 8 * ((bits_per_frame + 14 + 63) / 64)];
-        let total_frame_bytes = frame_bytes.len();
-        for f in 0..count {
-            let frame_addr: u32 = start_addr + (f as u32);
-            let frame_idx = c.frame_addr_to_idx(frame_addr);
-            self.ecc14 = ECC_INIT;
-            for b in frame_bytes.iter_mut() {
-                *b = 0;
-            }
-            for j in (0..bits_per_frame).rev() {
-                let ofs = (j + pad_bits) as usize;
-                let value = c.cram.get(frame_idx, j);
-                self.update_ecc(value);
-                if value {
-                    frame_bytes[(total_frame_bytes - 1) - (ofs / 8)] |= 1 << (ofs % 8);
-                }
-            }
-            let ecc = self.finalise_ecc();
-            frame_bytes[total_frame_bytes - 2] |= ((ecc >> 8) & 0x3F) as u8;
-            frame_bytes[total_frame_bytes - 1] |= (ecc & 0xFF) as u8;
-            self.write_comp_frame(&frame_bytes);
+        let frame_len = 8 * (c.data.bits_per_frame + 14).div_ceil(64);
+        // SED CRC covers the frame as it would be sent uncompressed
+        let sed_frame_bytes = (c.data.bits_per_frame + 14).div_ceil(8);
+        let comp_dic = self.comp_dic;
+        let frames = par_map(self.threads, &Self::frame_indices(c, start_addr, count)?, |&idx| {
+            let frame_bytes = encode_frame(c, idx, frame_len, true);
+            let compressed = compress_frame(&comp_dic, &frame_bytes);
+            (frame_bytes, compressed)
+        });
+        for (f, (frame_bytes, compressed)) in frames.iter().enumerate() {
+            self.update_sed_crc(&frame_bytes[frame_len - sed_frame_bytes..]);
+            self.write_bytes(compressed);
             if f == count - 1 {
                 self.insert_crc();
             }
@@ -397,31 +762,19 @@ impl BitstreamParser {
                 self.write_byte(0xFF);
             }
         }
+        Ok(())
     }
     fn compute_comp_dic(&mut self, c: &Chip) {
         // precompute all frames to discover the 16 most common byte values for the dictionary
-        let mut histogram = [0 as usize; 256]; 
-        let bits_per_frame = c.data.bits_per_frame;
-        let pad_bits = c.data.frame_ecc_bits + c.data.pad_bits_after_frame;
-        let mut frame_bytes = vec![0 as u8; (bits_per_frame + 14 + 7) / 8];
-        let total_frame_bytes = frame_bytes.len();
-        for f in 0..c.cram.frames {
-            self.ecc14 = ECC_INIT;
-            for b in frame_bytes.iter_mut() {
-                *b = 0;
-            }
-            for j in (0..bits_per_frame).rev() {
-                let ofs = (j + pad_bits) as usize;
-                let value = c.cram.get(f, j);
-                if value {
-                    frame_bytes[(total_frame_bytes - 1) - (ofs / 8)] |= 1 << (ofs % 8);
-                }
-            }
+        let mut histogram = [0usize; 256];
+        let frame_len = (c.data.bits_per_frame + 14).div_ceil(8);
+        let indices: Vec<usize> = (0..c.cram.frames).collect();
+        for frame_bytes in par_map(self.threads, &indices, |&f| encode_frame(c, f, frame_len, false)) {
             for b in &frame_bytes {
                 histogram[*b as usize] += 1;
             }
         }
-        let mut pairs = [(0 as usize, 0 as u8); 256];
+        let mut pairs = [(0usize, 0u8); 256];
         for i in 0..256 {
             pairs[i] = (histogram[i], i as u8);
         }
@@ -431,7 +784,7 @@ impl BitstreamParser {
         }
     }
 
-    fn write_ip_config(&mut self, c: &Chip) {
+    fn write_ip_config(&mut self, c: &Chip) -> Result<(), BitstreamError> {
         // Create continguous chunks
         let mut last_addr = None;
         let mut curr_chunk : Option<(u32, Vec<u8>)> = None;
@@ -472,7 +825,9 @@ impl BitstreamParser {
             // Padding
             self.write_padding(9);
             // Write data
-            let frame_size = c.get_bus_frame_size(start);
+            let frame_size = c
+                .get_bus_frame_size(start)
+                .ok_or(BitstreamError::NoSuchBusAddress { addr: start })?;
             let frame_count = (bytes.len() + frame_size - 1) / frame_size;
             self.write_byte(LSC_BUS_WRITE);
             self.write_byte(0xD0); // check CRC
@@ -487,6 +842,7 @@ impl BitstreamParser {
             }
             self.insert_crc();
         }
+        Ok(())
     }
     // "Push out" last 16 bits to get final crc16
     fn finalise_crc16(&mut self) {
@@ -501,12 +857,22 @@ impl BitstreamParser {
     }
 
     // Consume and check crc16
-    fn check_crc16(&mut self) {
+    fn check_crc16(&mut self) -> Result<(), BitstreamError> {
         self.finalise_crc16();
         let calc_crc16 = self.crc16;
-        let exp_crc16 = self.get_u16();
-        assert_eq!(calc_crc16, exp_crc16);
+        let offset = self.index;
+        let exp_crc16 = self.get_u16()?;
+        let ok = calc_crc16 == exp_crc16;
+        self.cmd_crc = Some(self.cmd_crc.unwrap_or(true) && ok);
         self.crc16 = CRC16_INIT;
+        if !ok {
+            return Err(BitstreamError::CrcMismatch {
+                offset,
+                expected: exp_crc16,
+                actual: calc_crc16,
+            });
+        }
+        Ok(())
     }
 
     // Finalise and insert CRC
@@ -516,18 +882,52 @@ impl BitstreamParser {
         self.crc16 = CRC16_INIT;
     }
 
+    // Record a parsed command for the disassembly
+    fn record_command(&mut self, start: usize, opcode: u8, info: String) {
+        let param_end = std::cmp::min(start + 4, self.index);
+        let params = self.data[start + 1..param_end].to_vec();
+        debug!("0x{:08X}: {} {}", start, command_name(opcode), info);
+        self.commands.push(BitstreamCommand {
+            offset: start,
+            opcode,
+            name: command_name(opcode).to_string(),
+            params,
+            payload_len: self.index.saturating_sub(start + 4),
+            crc_ok: self.cmd_crc,
+            info,
+        });
+    }
+
+    // Record a command that failed part way through, such as on a CRC mismatch, so that
+    // it is listed with the CRC result so far, and pass on the error
+    fn command_failed(&mut self, start: usize, opcode: u8, info: &str, err: BitstreamError) -> BitstreamError {
+        self.record_command(start, opcode, info.to_string());
+        err
+    }
+
+    // Commands parsed so far, in bitstream order
+    pub fn commands(&self) -> &[BitstreamCommand] {
+        &self.commands
+    }
+
+    // Result of checking the SED CRC, if the bitstream has one. The SED CRC algorithm
+    // is not yet confirmed against hardware, so a mismatch is not a parse error.
+    pub fn sed_crc_ok(&self) -> Option<bool> {
+        self.sed_crc_ok
+    }
+
     fn done(&self) -> bool {
         self.index >= self.data.len()
     }
 
     // Process bitstream container
     // Consumes metadata up to and including preamble
-    fn parse_container(&mut self) -> Result<BitstreamType, &'static str> {
+    fn parse_container(&mut self) -> Result<BitstreamType, BitstreamError> {
         let mut in_metadata = false;
         let mut curr_meta = String::new();
         while !self.done() {
             if self.check_preamble(&PREAMBLE) {
-                println!("bitstream start at {}", self.index);
+                debug!("bitstream start at {}", self.index);
                 return Ok(BitstreamType::NORMAL);
             }
             if !in_metadata && self.check_preamble(&COMMENT_START) {
@@ -550,10 +950,10 @@ impl BitstreamParser {
                 return Ok(BitstreamType::READBACK);
             }
             if in_metadata {
-                let ch = self.get_byte();
+                let ch = self.get_byte()?;
                 if ch == 0x00 {
                     if curr_meta.len() > 0 {
-                        println!("Metadata: {}", &curr_meta);
+                        debug!("Metadata: {}", &curr_meta);
                     }
                     self.metadata.push(curr_meta.to_string());
                     curr_meta.clear();
@@ -561,13 +961,13 @@ impl BitstreamParser {
                     curr_meta.push(ch as char);
                 }
             } else {
-                self.get_byte();
+                self.get_byte()?;
             }
         }
-        Err("failed to find preamble")
+        Err(BitstreamError::NoPreamble)
     }
 
-    fn decompress_frame(&mut self, dest: &mut [u8]) {
+    fn decompress_frame(&mut self, dest: &mut [u8]) -> Result<(), BitstreamError> {
         let mut read_data : u16 = 0;
         let mut remaining_bits : usize = 0;
         // Based on the implementation in trellis
@@ -580,14 +980,14 @@ impl BitstreamParser {
 
         for i in 0..dest.len() {
             if remaining_bits == 0 {
-                read_data = self.get_byte() as u16;
+                read_data = self.get_byte()? as u16;
                 remaining_bits = 8;
             }
             let mut next_bit = ((read_data >> (remaining_bits - 1)) & 1) == 1;
             remaining_bits -= 1;
             dest[i] = if next_bit {
                 if remaining_bits < 5 {
-                    read_data = (read_data << 8) | (self.get_byte() as u16);
+                    read_data = (read_data << 8) | (self.get_byte()? as u16);
                     remaining_bits += 8;
                 }
                 next_bit = (read_data >> (remaining_bits-1) & 1) == 1;
@@ -596,7 +996,7 @@ impl BitstreamParser {
                     // 11 xxxx xxxx: Literal byte, just read the next 8 bits & use that
                     // we consumed 10 bits total
                     if remaining_bits < 8 {
-                        read_data = (read_data << 8) | (self.get_byte() as u16);
+                        read_data = (read_data << 8) | (self.get_byte()? as u16);
                         remaining_bits += 8;
                     }
                     let literal = ((read_data >> (remaining_bits - 8)) & 0xff) as u8;
@@ -614,100 +1014,104 @@ impl BitstreamParser {
                 0
             };
         }
+    Ok(())
     }
 
-    fn write_comp_frame(&mut self, frame: &[u8]) {
-        let mut buffer : u8 = 0;
-        let mut bits_in_buffer : usize = 0;
-        for b in frame {
-            let mut add_bit = |s: &mut Self, bit: bool| {
-                if bit {
-                    buffer |= 1 << (7 - bits_in_buffer);
-                }
-                bits_in_buffer += 1;
-                if bits_in_buffer == 8 {
-                    s.write_byte(buffer);
-                    bits_in_buffer = 0;
-                    buffer = 0;
-                }
-            };
-            let mut add_bits = |s: &mut Self, value: u16, len: usize| {
-                for i in (0..len).rev() {
-                    add_bit(s, (value & (1 << i)) != 0);
-                }
-
-            };
-            if *b == 0 {
-                // 0 byte -> 0 bit
-                add_bit(self, false);
-                continue;
-            }
-            let mut dict_found = false;
-            for i in 0..16 {
-                if self.comp_dic[i] == *b {
-                    // dictionary entry -> 0b10xxxx
-                    add_bits(self, 0b10, 2);
-                    add_bits(self, (15 - i) as u16, 4);
-                    dict_found = true;
-                    break;
-                }
+    // Decode (address, index, bytes) of frames read from the bitstream into the chip
+    fn load_frames(&mut self, chip: &mut Chip, frames: &[(u32, usize, Vec<u8>)], bits_per_frame: usize, pad_bits: usize) {
+        let decoded = par_map(self.threads, frames, |(_addr, _idx, frame_bytes)| {
+            decode_frame(frame_bytes, bits_per_frame, pad_bits)
+        });
+        for ((addr, idx, frame_bytes), set_bits) in frames.iter().zip(decoded.iter()) {
+            for &j in set_bits.iter() {
+                chip.cram.set(*idx, j, true);
+                trace!("F0x{:08x}B{:04}", addr, j);
             }
-            if dict_found {
-                continue;
+            let parity = ((frame_bytes[frame_bytes.len() - 2] as u16) << 8
+                | (frame_bytes[frame_bytes.len() - 1] as u16))
+                & 0x3FFF;
+
+            // ECC calculation here is actually occasionally unsound,
+            // as LUT RAM initialisation is masked from ECC calculation
+            // as it changes at runtime. So it is checked once the whole
+            // chip is known, in check_frame_ecc.
+            self.frame_ecc.push((*addr, *idx, parity));
+
+            if log_enabled!(Level::Trace) {
+                let exp_parity = frame_ecc((0..bits_per_frame).rev().map(|j| set_bits.contains(&j)));
+                trace!("F0x{:08x}P{:014b}E{:014b}", addr, parity, exp_parity);
             }
-            // literal -> 0b11xxxxxxxx
-            add_bits(self, 0b11, 2);
-            add_bits(self, *b as u16, 8);
-        }
-        if bits_in_buffer != 0 {
-            self.write_byte(buffer);
         }
     }
 
     // Parse the bitstream itself
-    fn parse_bitstream(&mut self, db: &mut Database) -> Result<Chip, &'static str> {
+    fn parse_bitstream(&mut self, db: &mut Database) -> Result<Chip, BitstreamError> {
         let mut curr_frame = 0;
         let mut bus_addr = 0;
-        let mut curr_chip = None;
+        let mut curr_chip: Option<Chip> = None;
         while !self.done() {
-            let cmd = self.get_opcode_byte();
+            let start = self.index;
+            let cmd = self.get_opcode_byte()?;
+            if cmd == DUMMY {
+                continue;
+            }
+            let info;
+            self.cmd_crc = None;
             match cmd {
+                SPI_MODE => {
+                    let mode = self.get_byte()?;
+                    self.skip_bytes(2)?;
+                    info = format!("set SPI read mode to 0x{:02X}", mode);
+                }
+                JUMP => {
+                    self.skip_bytes(3)?;
+                    let addr = self.get_u32()?;
+                    info = format!("jump to flash address 0x{:08X}", addr);
+                }
                 LSC_RESET_CRC => {
-                    println!("reset CRC");
-                    self.skip_bytes(3);
+                    info = "reset CRC".to_string();
+                    self.skip_bytes(3)?;
                     self.crc16 = CRC16_INIT;
                 }
                 LSC_PROG_CNTRL0 => {
-                    self.skip_bytes(3);
-                    let ctrl0 = self.get_u32();
-                    println!("set CTRL0 to 0x{:08X}", ctrl0);
+                    self.skip_bytes(3)?;
+                    let ctrl0 = self.get_u32()?;
+                    info = format!("set CTRL0 to 0x{:08X}", ctrl0);
+                    if let Some(chip) = curr_chip.as_mut() {
+                        chip.ctrl0 = ctrl0;
+                    }
                 }
                 VERIFY_ID => {
-                    self.skip_bytes(3);
-                    let idcode = self.get_u32();
-                    let mut chip = Chip::from_idcode(db, idcode);
+                    self.skip_bytes(3)?;
+                    let id_offset = self.index;
+                    let idcode = self.get_u32()?;
+                    let mut chip = Chip::from_idcode(db, idcode).ok_or(BitstreamError::UnknownIdcode {
+                        offset: id_offset,
+                        idcode,
+                    })?;
                     chip.metadata = self.metadata.clone();
                     curr_chip = Some(chip);
-                    println!("check IDCODE is 0x{:08X}", idcode);
+                    info = format!("check IDCODE is 0x{:08X}", idcode);
                 }
                 LSC_INIT_ADDRESS => {
-                    self.skip_bytes(3);
-                    println!("reset frame address");
+                    self.skip_bytes(3)?;
+                    info = "reset frame address".to_string();
                     curr_frame = 0;
                 }
                 LSC_WRITE_ADDRESS => {
-                    self.skip_bytes(3);
-                    curr_frame = self.get_u32();
-                    println!("set frame address to 0x{:08X}", curr_frame);
+                    self.skip_bytes(3)?;
+                    curr_frame = self.get_u32()?;
+                    info = format!("set frame address to 0x{:08X}", curr_frame);
                 }
                 LSC_AUTH_CTRL => {
-                    self.skip_bytes(3);
-                    self.skip_bytes(64);
-                    println!("LSC_AUTH_CTRL (bitstream is probably signed!)");
+                    self.skip_bytes(3)?;
+                    self.skip_bytes(64)?;
+                    info = "LSC_AUTH_CTRL (bitstream is probably signed!)".to_string();
                 }
                 LSC_PROG_INCR_RTI => {
-                    let cfg = self.get_byte();
-                    let count = self.get_u16();
+                    let cfg_offset = self.index;
+                    let cfg = self.get_byte()?;
+                    let count = self.get_u16()?;
                     let bits_per_frame: usize;
                     let pad_bits: usize;
                     let chip: &mut Chip;
@@ -718,96 +1122,93 @@ impl BitstreamParser {
                             chip = ch;
                         }
                         None => {
-                            return Err("got bitstream before idcode");
+                            return Err(BitstreamError::MissingIdcode { offset: start });
                         }
                     }
-                    println!("write {} frames at 0x{:08x}", count, curr_frame);
-                    let mut frame_bytes = vec![0 as u8; (bits_per_frame + 14 + 7) / 8];
-                    assert_eq!(cfg, 0x91);
+                    info = format!("write {} frames at 0x{:08x}", count, curr_frame);
+                    let frame_len = (bits_per_frame + 14).div_ceil(8);
+                    if cfg != 0x91 {
+                        return Err(BitstreamError::BadByte { offset: cfg_offset, expected: 0x91, actual: cfg });
+                    }
+                    let mut frames = Vec::new();
                     for _ in 0..count {
-                        self.copy_bytes(&mut frame_bytes);
-                        self.ecc14 = ECC_INIT;
-                        for j in (0..bits_per_frame).rev() {
-                            let ofs = (j + pad_bits) as usize;
-                            if ((frame_bytes[(frame_bytes.len() - 1) - (ofs / 8)] >> (ofs % 8))
-                                & 0x01)
-                                == 0x01
-                            {
-                                let decoded_frame = chip.frame_addr_to_idx(curr_frame);
-                                if decoded_frame < chip.cram.frames {
-                                    chip.cram.set(decoded_frame, j, true);
-                                }
-                                if self.verbose {
-                                    println!("F0x{:08x}B{:04}", curr_frame, j);
-                                }
-                                self.update_ecc(true);
-                            } else {
-                                self.update_ecc(false);
-                            }
-                        }
-                        let parity = ((frame_bytes[frame_bytes.len() - 2] as u16) << 8
-                            | (frame_bytes[frame_bytes.len() - 1] as u16))
-                            & 0x3FFF;
-                        let exp_parity = self.finalise_ecc();
-
-                        // ECC calculation here is actually occasionally unsound,
-                        // as LUT RAM initialisation is masked from ECC calculation
-                        // as it changes at runtime. But it is too early to check this here.
-
-                        if self.verbose {
-                            println!("F0x{:08x}P{:014b}E{:014b}", curr_frame, parity, exp_parity);
-                        }
-                        self.check_crc16();
-                        let d = self.get_byte();
-                        assert_eq!(d, 0xFF);
-                        curr_frame += 1;
+                        let frame_offset = self.index;
+                        let decoded_frame = chip.frame_addr_to_idx(curr_frame).ok_or(
+                            BitstreamError::BadFrameAddress { offset: frame_offset, addr: curr_frame })?;
+                        let mut frame_bytes = vec![0u8; frame_len];
+                        self.copy_bytes(&mut frame_bytes)?;
+                        self.update_sed_crc(&frame_bytes);
+                        self.check_crc16().map_err(|e| self.command_failed(start, cmd, &info, e))?;
+                        self.expect_byte(0xFF)?;
+                        frames.push((curr_frame, decoded_frame, frame_bytes));
+                        curr_frame = curr_frame.wrapping_add(1);
                     }
+                    self.load_frames(chip, &frames, bits_per_frame, pad_bits);
                 }
                 LSC_POWER_CTRL => {
-                    self.skip_bytes(2);
-                    let pwr = self.get_byte();
-                    println!("power control: {}", pwr);
+                    self.skip_bytes(2)?;
+                    let pwr = self.get_byte()?;
+                    info = format!("power control: {}", pwr);
+                    if let Some(chip) = curr_chip.as_mut() {
+                        chip.power_ctrl = pwr;
+                    }
                 }
                 ISC_PROGRAM_USERCODE => {
-                    let cmp_crc = self.get_byte() & 0x80 == 0x80;
-                    self.skip_bytes(2);
-                    let usercode = self.get_u32();
-                    println!("set usercode to 0x{:08X}", usercode);
+                    let cmp_crc = self.get_byte()? & 0x80 == 0x80;
+                    self.skip_bytes(2)?;
+                    let usercode = self.get_u32()?;
+                    info = format!("set usercode to 0x{:08X}", usercode);
+                    if let Some(chip) = curr_chip.as_mut() {
+                        chip.usercode = usercode;
+                    }
                     if cmp_crc {
-                        self.check_crc16();
+                        self.check_crc16().map_err(|e| self.command_failed(start, cmd, &info, e))?;
                     }
                 }
                 LSC_BUS_ADDRESS => {
-                    self.skip_bytes(3);
-                    bus_addr = self.get_u32();
+                    self.skip_bytes(3)?;
+                    bus_addr = self.get_u32()?;
+                    info = format!("set bus address to 0x{:08X}", bus_addr);
+                    // Undo the LRAM address fixup applied in write_ip_config
+                    if bus_addr & 0xFF000000 == 0x2E000000 {
+                        let ls = bus_addr & 0x1FFFF;
+                        let ms = bus_addr & 0xFFFE0000;
+                        bus_addr = ms | ((ls * 10) / 8);
+                    }
                 }
                 LSC_BUS_WRITE => {
-                    let config = self.get_byte();
+                    let config = self.get_byte()?;
                     let cmp_crc = config & 0x80 == 0x80;
-                    let frame_count = self.get_u16() as usize;
+                    let frame_count = self.get_u16()? as usize;
                     let chip = curr_chip
                         .as_mut()
-                        .expect("got bus write without chip setup");
-                    let byte_count = frame_count * chip.get_bus_frame_size(bus_addr);
+                        .ok_or(BitstreamError::MissingIdcode { offset: start })?;
+                    let byte_count = frame_count * chip.get_bus_frame_size(bus_addr).ok_or(
+                        BitstreamError::BadBusAddress { offset: start, addr: bus_addr })?;
+                    info = format!("write {} bytes at bus address 0x{:08X}", byte_count, bus_addr);
                     for _i in 0..byte_count {
-                        chip.ipconfig.insert(bus_addr, self.get_byte());
-                        bus_addr += 1;
+                        chip.ipconfig.insert(bus_addr, self.get_byte()?);
+                        bus_addr = bus_addr.wrapping_add(1);
                     }
                     if cmp_crc {
-                        self.check_crc16();
+                        self.check_crc16().map_err(|e| self.command_failed(start, cmd, &info, e))?;
                     }
                 }
                 LSC_WRITE_COMP_DIC => {
-                    self.skip_bytes(3);
-                    let mut tmp = [0 as u8; 16];
-                    self.copy_bytes(&mut tmp);
+                    self.skip_bytes(3)?;
+                    let mut tmp = [0u8; 16];
+                    self.copy_bytes(&mut tmp)?;
                     self.comp_dic = tmp;
-                    println!("compression dictionary: {}",
+                    info = format!("compression dictionary: {}",
                         self.comp_dic.iter().map(|x| format!("{:02x}", x)).collect::<Vec<_>>().join(" "));
+                    if let Some(chip) = curr_chip.as_mut() {
+                        chip.settings.insert("compress".to_string(), "1".to_string());
+                    }
                 }
                 LSC_PROG_INCR_CMP => {
-                    let cfg = self.get_byte();
-                    let count = self.get_u16();
+                    let cfg_offset = self.index;
+                    let cfg = self.get_byte()?;
+                    let count = self.get_u16()?;
                     let bits_per_frame: usize;
                     let pad_bits: usize;
                     let chip: &mut Chip;
@@ -818,87 +1219,92 @@ impl BitstreamParser {
                             chip = ch;
                         }
                         None => {
-                            return Err("got bitstream before idcode");
+                            return Err(BitstreamError::MissingIdcode { offset: start });
                         }
                     }
-                    println!("write {} compressed frames at 0x{:08x}", count, curr_frame);
-                    let mut frame_bytes = vec![0 as u8; 8 * ((bits_per_frame + 14 + 63) / 64)];
-                    assert_eq!(cfg, 0xD4);
+                    info = format!("write {} compressed frames at 0x{:08x}", count, curr_frame);
+                    let frame_len = 8 * (bits_per_frame + 14).div_ceil(64);
+                    let sed_frame_bytes = (bits_per_frame + 14).div_ceil(8);
+                    if cfg != 0xD4 {
+                        return Err(BitstreamError::BadByte { offset: cfg_offset, expected: 0xD4, actual: cfg });
+                    }
+                    let mut frames = Vec::new();
                     for frame in 0..count {
-                        self.decompress_frame(&mut frame_bytes);
-                        if self.verbose {
-                            println!("decompressed: {}",
-                                frame_bytes.iter().map(|x| format!("{:02x}", x)).collect::<Vec<_>>().join(" "));
-                        }
-                        self.ecc14 = ECC_INIT;
-                        for j in (0..bits_per_frame).rev() {
-                            let ofs = (j + pad_bits) as usize;
-                            if ((frame_bytes[(frame_bytes.len() - 1) - (ofs / 8)] >> (ofs % 8))
-                                & 0x01)
-                                == 0x01
-                            {
-                                let decoded_frame = chip.frame_addr_to_idx(curr_frame);
-                                if decoded_frame < chip.cram.frames {
-                                    chip.cram.set(decoded_frame, j, true);
-                                }
-                                if self.verbose {
-                                    println!("F0x{:08x}B{:04}", curr_frame, j);
-                                }
-                                self.update_ecc(true);
-                            } else {
-                                self.update_ecc(false);
-                            }
-                        }
-                        let parity = ((frame_bytes[frame_bytes.len() - 2] as u16) << 8
-                            | (frame_bytes[frame_bytes.len() - 1] as u16))
-                            & 0x3FFF;
-                        let exp_parity = self.finalise_ecc();
-
-                        // ECC calculation here is actually occasionally unsound,
-                        // as LUT RAM initialisation is masked from ECC calculation
-                        // as it changes at runtime. But it is too early to check this here.
-
-                        if self.verbose {
-                            println!("F0x{:08x}P{:014b}E{:014b}", curr_frame, parity, exp_parity);
-                        }
+                        let frame_offset = self.index;
+                        let decoded_frame = chip.frame_addr_to_idx(curr_frame).ok_or(
+                            BitstreamError::BadFrameAddress { offset: frame_offset, addr: curr_frame })?;
+                        let mut frame_bytes = vec![0u8; frame_len];
+                        self.decompress_frame(&mut frame_bytes)?;
+                        trace!("decompressed: {}",
+                            frame_bytes.iter().map(|x| format!("{:02x}", x)).collect::<Vec<_>>().join(" "));
+                        self.update_sed_crc(&frame_bytes[frame_len - sed_frame_bytes..]);
                         if frame == count - 1 {
-                            self.check_crc16();
+                            self.check_crc16().map_err(|e| self.command_failed(start, cmd, &info, e))?;
                         }
                         for _ in 0..4 {
-                            let d = self.get_byte();
-                            assert_eq!(d, 0xFF);
+                            self.expect_byte(0xFF)?;
                         }
-                        curr_frame += 1;
+                        frames.push((curr_frame, decoded_frame, frame_bytes));
+                        curr_frame = curr_frame.wrapping_add(1);
+                    }
+                    self.load_frames(chip, &frames, bits_per_frame, pad_bits);
+                }
+                LSC_PROG_SED_CRC => {
+                    self.skip_bytes(3)?;
+                    let exp_sed_crc = self.get_u32()?;
+                    info = format!("SED CRC 0x{:08X}", exp_sed_crc);
+                    let ok = exp_sed_crc == self.sed_crc;
+                    self.cmd_crc = Some(ok);
+                    self.sed_crc_ok = Some(ok);
+                    if !ok {
+                        warn!(
+                            "SED CRC mismatch: bitstream has 0x{:08X}, frame data gives 0x{:08X}",
+                            exp_sed_crc, self.sed_crc
+                        );
+                    }
+                    if let Some(chip) = curr_chip.as_mut() {
+                        chip.settings.insert("sed_crc".to_string(), "1".to_string());
+                    }
+                }
+                ISC_PROGRAM_SECURITY => {
+                    self.skip_bytes(3)?;
+                    info = "program security (readback locked)".to_string();
+                    if let Some(chip) = curr_chip.as_mut() {
+                        chip.settings.insert("secure".to_string(), "1".to_string());
                     }
                 }
                 ISC_PROGRAM_DONE => {
-                    self.skip_bytes(3);
-                    println!("done");
+                    self.skip_bytes(3)?;
+                    info = "done".to_string();
                 }
-                DUMMY => {}
                 _ => {
-                    println!("unknown command 0x{:02X} at {}", cmd, self.index);
-                    return Err("unknown bitstream command");
+                    info = format!("unknown command 0x{:02X} at {}", cmd, start);
+                    self.record_command(start, cmd, info);
+                    return Err(BitstreamError::UnknownCommand { offset: start, opcode: cmd });
                 }
             }
+            self.record_command(start, cmd, info);
         }
         match curr_chip {
             Some(x) => Ok(x),
-            None => Err("missing bitstream content"),
+            None => Err(BitstreamError::MissingIdcode { offset: self.index }),
         }
     }
 
-    fn parse_readback_bistream(&mut self, db: &mut Database) -> Result<Chip, &'static str> {
+    fn parse_readback_bistream(&mut self, db: &mut Database) -> Result<Chip, BitstreamError> {
         // 4 byte IDCODE
-        let idcode = self.get_u32();
-        let mut chip = Chip::from_idcode(db, idcode);
+        let id_offset = self.index;
+        let idcode = self.get_u32()?;
+        let mut chip = Chip::from_idcode(db, idcode).ok_or(BitstreamError::UnknownIdcode {
+            offset: id_offset,
+            idcode,
+        })?;
         // 4 bytes 00 padding
-        self.skip_bytes(4);
+        self.skip_bytes(4)?;
         // 20 bytes FF padding
-        self.skip_bytes(20);
+        self.skip_bytes(20)?;
         
-        let mut frame_bytes = vec![0 as u8; (chip.data.bits_per_frame + 14 + 7) / 8];
-        let mut padding = [0 as u8; 4];
+        let mut frame_bytes = vec![0u8; (chip.data.bits_per_frame + 14 + 7) / 8];
 
         for i in 0..chip.data.frames {
             let frame_index = if i < 16 {
@@ -915,33 +1321,327 @@ impl BitstreamParser {
                 ((chip.tap_frame_count - 1) - (i - (chip.data.frames - chip.tap_frame_count))) + 16
             };
             // 4 bytes dummy
-            self.copy_bytes(&mut padding);
-            assert_eq!(padding, [0xFF, 0xFF, 0xFF, 0xFF]);
+            for _ in 0..4 {
+                self.expect_byte(0xFF)?;
+            }
             // frame data
-            self.copy_bytes(&mut frame_bytes);
+            let frame_offset = self.index;
+            self.copy_bytes(&mut frame_bytes)?;
             for j in 0..(chip.data.bits_per_frame + chip.data.pad_bits_after_frame) {
                 // TODO: bit ordering inside frames
-                let ofs = (14 + j) as usize;
+                let ofs = 14 + j;
                 let val = ((frame_bytes[(frame_bytes.len() - 1) - (ofs / 8)] >> (ofs % 8)) & 0x01) == 0x01;
                 if j < chip.data.bits_per_frame {
                     if val {
                         chip.cram.set(frame_index, j, true);
                     }
-                } else {
+                } else if !val {
                     // padding bit, should be one
-                    assert!(val);
+                    return Err(BitstreamError::BadPadding { offset: frame_offset, frame: i, bit: j });
                 }
             }
         }
         Ok(chip)
     }
 
-    pub fn parse(&mut self, db: &mut Database) -> Result<Chip, &'static str> {
+    // Check the ECC of parsed frames, now dynamic bits are known, returning the
+    // addresses of frames that don't match
+    fn check_frame_ecc(&self, c: &Chip) -> Vec<u32> {
+        let exp_parities = par_map(self.threads, &self.frame_ecc, |(_addr, idx, _parity)| chip_frame_ecc(c, *idx));
+        let mut mismatches = Vec::new();
+        for ((addr, _idx, parity), exp_parity) in self.frame_ecc.iter().zip(exp_parities) {
+            if *parity != exp_parity {
+                warn!(
+                    "ECC mismatch in frame 0x{:08x}: bitstream has {:014b}, calculated {:014b}",
+                    addr, parity, exp_parity
+                );
+                mismatches.push(*addr);
+            }
+        }
+        mismatches
+    }
+
+    pub fn parse(&mut self, db: &mut Database) -> Result<Chip, BitstreamError> {
         let typ = self.parse_container()?;
         let c = match typ {
-            BitstreamType::NORMAL => self.parse_bitstream(db)?,
-            BitstreamType::READBACK => self.parse_readback_bistream(db)?,
+            BitstreamType::NORMAL => {
+                let mut c = self.parse_bitstream(db)?;
+                c.update_ecc_mask(db);
+                self.check_frame_ecc(&c);
+                c
+            }
+            BitstreamType::READBACK => {
+                let mut c = self.parse_readback_bistream(db)?;
+                c.update_ecc_mask(db);
+                c
+            }
         };
         Ok(c)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::{read_memory, write_memory};
+    use std::fs;
+
+    const FRAMES: usize = 96;
+    const BITS: usize = 100;
+
+    // A tiletype with a LUT initialisation word that is dynamic in DPRAM mode, and an
+    // enum whose default sets a bit that the other option clears
+    const DPRAM_TILETYPE: &str = r#"(
+        pips: {},
+        words: {"SLICEA.K0.INIT": (bits: [[(frame: 1, bit: 10, invert: false)], [(frame: 1, bit: 11, invert: false)]])},
+        enums: {
+            "SLICEA.MODE": (options: {"LOGIC": [], "DPRAM": [(frame: 0, bit: 40, invert: false)]}),
+            "REG0.SD": (options: {"0": [(frame: 2, bit: 20, invert: true)], "1": [(frame: 2, bit: 20, invert: false)]}, default: Some("1")),
+        },
+        conns: {},
+        dynamic: {"SLICEA.K0.INIT": (enum_name: "SLICEA.MODE", options: ["DPRAM"])},
+    )"#;
+
+    // Write a small database with one device, split into four tiles
+    fn write_database(root: &std::path::Path) {
+        let dev = root.join("LIFCL/LIFCL-40");
+        fs::create_dir_all(&dev).unwrap();
+        fs::create_dir_all(root.join("LIFCL/tiletypes")).unwrap();
+        fs::write(
+            root.join("devices.json"),
+            format!(
+                concat!(
+                    r#"{{"families":{{"LIFCL":{{"devices":{{"LIFCL-40":{{"packages":[],"frames":{},"#,
+                    r#""bits_per_frame":{},"pad_bits_after_frame":2,"pad_bits_before_frame":0,"#,
+                    r#""frame_ecc_bits":14,"max_row":2,"max_col":2,"col_bias":0,"fuzz":false,"#,
+                    r#""variants":{{"ES":{{"idcode":272961603}}}}}}}}}}}}}}"#
+                ),
+                FRAMES, BITS
+            ),
+        )
+        .unwrap();
+        let tiles: Vec<String> = (0..4)
+            .map(|i| {
+                format!(
+                    r#""R{}C{}:PLC":{{"tiletype":"PLC","x":{},"y":{},"start_bit":{},"start_frame":{},"bits":{},"frames":{}}}"#,
+                    i / 2,
+                    i % 2,
+                    i % 2,
+                    i / 2,
+                    (i % 2) * BITS / 2,
+                    (i / 2) * FRAMES / 2,
+                    BITS / 2,
+                    FRAMES / 2
+                )
+            })
+            .collect();
+        fs::write(dev.join("tilegrid.json"), format!(r#"{{"tiles":{{{}}}}}"#, tiles.join(","))).unwrap();
+        fs::write(
+            dev.join("baseaddr.json"),
+            concat!(
+                r#"{"regions":{"EBR_WID0":{"addr":536870912,"abits":11},"EBR_WID1":{"addr":536872960,"abits":11},"#,
+                r#""LRAM_0":{"addr":771751936,"abits":17}}}"#
+            ),
+        )
+        .unwrap();
+        fs::write(root.join("LIFCL/tiletypes/PLC.ron"), DPRAM_TILETYPE).unwrap();
+        fs::create_dir_all(root.join("LIFCL/iptypes")).unwrap();
+        fs::write(root.join("LIFCL/iptypes/EBR_INIT.ron"), ip_tiletype(&["INITVAL_00", "INITVAL_01"])).unwrap();
+        fs::write(root.join("LIFCL/iptypes/LRAM_INIT.ron"), ip_tiletype(&["INITVAL_00"])).unwrap();
+    }
+
+    // An IP type with 40-bit words, each packed LSB first into the next five bytes
+    fn ip_tiletype(words: &[&str]) -> String {
+        let words: Vec<String> = words
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let bits: Vec<String> = (0..40)
+                    .map(|b| format!("[(frame: {}, bit: {}, invert: false)]", i * 5 + b / 8, b % 8))
+                    .collect();
+                format!(r#""{}": (bits: [{}])"#, name, bits.join(", "))
+            })
+            .collect();
+        format!("(pips: {{}}, words: {{{}}}, enums: {{}}, conns: {{}})", words.join(", "))
+    }
+
+    // Create the test database in a temporary directory named for the test, as tests
+    // run in parallel
+    fn test_database(name: &str) -> (std::path::PathBuf, Database) {
+        let root = std::env::temp_dir().join(format!("prjoxide-test-{}-{}", name, std::process::id()));
+        write_database(&root);
+        let db = Database::new(root.to_str().unwrap());
+        (root, db)
+    }
+
+    // A chip with a fixed pattern of bits set
+    fn test_chip(db: &mut Database) -> Chip {
+        let mut chip = Chip::from_name_variant(db, "LIFCL-40", "ES");
+        for f in 0..FRAMES {
+            for b in 0..BITS {
+                chip.cram.set(f, b, (f * 31 + b * 17) % 7 == 0);
+            }
+        }
+        chip.cram_to_tiles();
+        chip
+    }
+
+    #[test]
+    fn output_independent_of_threads() {
+        let (root, mut db) = test_database("threads");
+        let mut chip = test_chip(&mut db);
+        for compress in ["0", "1"].iter() {
+            chip.settings.insert("compress".to_string(), compress.to_string());
+            let single = BitstreamParser::serialise_chip_threads(&chip, 1).unwrap();
+            let multi = BitstreamParser::serialise_chip_threads(&chip, 4).unwrap();
+            assert!(single == multi, "bitstreams differ with compress={}", compress);
+            let mut parser = BitstreamParser::new(&multi);
+            parser.set_threads(4);
+            let parsed = parser.parse(&mut db).unwrap();
+            assert!(parsed.cram.delta(&chip.cram).is_empty());
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn crc_failure_recorded() {
+        let (root, mut db) = test_database("crc");
+        let chip = test_chip(&mut db);
+        let mut bitstream = BitstreamParser::serialise_chip(&chip).unwrap();
+        let mut parser = BitstreamParser::new(&bitstream);
+        parser.parse(&mut db).unwrap();
+        let frames = parser.commands().iter().find(|c| c.name == "LSC_PROG_INCR_RTI").unwrap();
+        assert_eq!(frames.crc_ok, Some(true));
+        // Flip a byte of the first frame
+        let offset = frames.offset + 4;
+        bitstream[offset] ^= 0x01;
+        let mut parser = BitstreamParser::new(&bitstream);
+        assert!(matches!(parser.parse(&mut db), Err(BitstreamError::CrcMismatch { .. })));
+        let last = parser.commands().last().unwrap();
+        assert_eq!(last.name, "LSC_PROG_INCR_RTI");
+        assert_eq!(last.crc_ok, Some(false));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn secure_round_trip() {
+        let (root, mut db) = test_database("secure");
+        let mut chip = test_chip(&mut db);
+        chip.usercode = 0x1234_5678;
+        chip.settings.insert("secure".to_string(), "1".to_string());
+        let bitstream = BitstreamParser::serialise_chip(&chip).unwrap();
+        let mut parser = BitstreamParser::new(&bitstream);
+        let parsed = parser.parse(&mut db).unwrap();
+        assert_eq!(parsed.settings.get("secure").map(String::as_str), Some("1"));
+        assert_eq!(parsed.usercode, 0x1234_5678);
+        // Security is programmed after the usercode and before DONE
+        let names: Vec<&str> = parser.commands().iter().map(|c| c.name.as_str()).collect();
+        let tail = &names[names.len() - 3..];
+        assert_eq!(tail, ["ISC_PROGRAM_USERCODE", "ISC_PROGRAM_SECURITY", "ISC_PROGRAM_DONE"]);
+        let security = &parser.commands()[names.len() - 2];
+        assert_eq!(security.params, [0, 0, 0]);
+        assert_eq!(security.payload_len, 0);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn unwritable_ip_address() {
+        let (root, mut db) = test_database("ipaddr");
+        let mut chip = test_chip(&mut db);
+        chip.ipconfig.insert(0x5000_0000, 0x12);
+        assert!(matches!(
+            BitstreamParser::serialise_chip(&chip),
+            Err(BitstreamError::NoSuchBusAddress { addr: 0x5000_0000 })
+        ));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn dynamic_bits_masked_from_ecc() {
+        let (root, mut db) = test_database("dynamic");
+        let mut chip = Chip::from_name_variant(&mut db, "LIFCL-40", "ES");
+        // LUT RAM in the first tile, with the second tile's LUT left as logic
+        let set_init = |chip: &mut Chip, init: [bool; 2]| {
+            chip.tiles[0].cram.set(1, 10, init[0]);
+            chip.tiles[0].cram.set(1, 11, init[1]);
+            chip.tiles_to_cram();
+        };
+        chip.tiles[0].cram.set(0, 40, true);
+        chip.tiles[1].cram.set(1, 10, true);
+        set_init(&mut chip, [true, false]);
+        chip.update_ecc_mask(&mut db);
+        let (f0, b0) = (chip.tiles[0].start_frame + 1, chip.tiles[0].start_bit + 10);
+        let (f1, b1) = (chip.tiles[1].start_frame + 1, chip.tiles[1].start_bit + 10);
+        assert!(chip.ecc_mask.get(f0, b0) && chip.ecc_mask.get(f0, b0 + 1));
+        assert!(!chip.ecc_mask.get(f1, b1) && !chip.ecc_mask.get(f1, b1 + 1));
+
+        // The frame ECC doesn't depend on the RAM contents
+        let ecc = chip_frame_ecc(&chip, f0);
+        let mut unmasked = chip.clone();
+        unmasked.ecc_mask = BitMatrix::new(FRAMES, BITS);
+        assert_ne!(chip_frame_ecc(&unmasked, f0), ecc);
+        let mut changed = chip.clone();
+        set_init(&mut changed, [false, true]);
+        assert_eq!(chip_frame_ecc(&changed, f0), ecc);
+
+        // Frames written with the mask match on parsing; without it, the LUT RAM frame doesn't
+        let mut parser = BitstreamParser::new(&BitstreamParser::serialise_chip(&changed).unwrap());
+        let parsed = parser.parse(&mut db).unwrap();
+        assert!(parser.check_frame_ecc(&parsed).is_empty());
+        let mut unmasked = changed.clone();
+        unmasked.ecc_mask = BitMatrix::new(FRAMES, BITS);
+        let mut parser = BitstreamParser::new(&BitstreamParser::serialise_chip(&unmasked).unwrap());
+        let parsed = parser.parse(&mut db).unwrap();
+        let frame_addr = |idx: usize| (0..0x10000).find(|&a| parsed.frame_addr_to_idx(a) == Some(idx)).unwrap();
+        assert_eq!(parser.check_frame_ecc(&parsed), [frame_addr(f0)]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn memory_round_trip() {
+        let (root, mut db) = test_database("memory");
+        let mut chip = test_chip(&mut db);
+        // Words 1 and 127 of the LRAM are at non-zero multiples of the INITVAL stride, so
+        // their bus addresses are rescaled when written and parsed
+        let ebr: Vec<u64> = vec![0x12_3456_789A, 0xFF_0000_0001];
+        let mut lram = vec![0u64; 128];
+        lram[0] = 0x01_0203_0405;
+        lram[1] = 0xA5_5A5A_A55A;
+        lram[127] = 0xFF_FFFF_FFFF;
+        write_memory(&mut chip, &mut db, "EBR_WID1", &ebr).unwrap();
+        write_memory(&mut chip, &mut db, "LRAM_0", &lram).unwrap();
+        let bitstream = BitstreamParser::serialise_chip(&chip).unwrap();
+        let mut parser = BitstreamParser::new(&bitstream);
+        let parsed = parser.parse(&mut db).unwrap();
+        assert_eq!(parsed.ipconfig, chip.ipconfig);
+        // The LRAM addresses in the bitstream count 32 bits per 40-bit word
+        let lram_addrs: Vec<String> = parser
+            .commands()
+            .iter()
+            .filter(|c| c.name == "LSC_BUS_ADDRESS" && c.info.contains("0x2E"))
+            .map(|c| c.info.clone())
+            .collect();
+        assert_eq!(lram_addrs[1], "set bus address to 0x2E000200");
+        assert_eq!(read_memory(&parsed, &mut db, "EBR_WID1").unwrap().words, ebr);
+        assert_eq!(read_memory(&parsed, &mut db, "LRAM_0").unwrap().words, lram);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn cleared_option_round_trip() {
+        let (root, mut db) = test_database("default");
+        let chip = Chip::from_name_variant(&mut db, "LIFCL-40", "ES");
+        // The non-default option only clears a bit, so is what cleared CRAM decodes to
+        for &(set, option) in [(false, "0"), (true, "1")].iter() {
+            let mut tile = chip.tiles[0].clone();
+            tile.cram.set(2, 20, set);
+            let ft = tile.decode_fasm(&mut db);
+            assert_eq!(ft.enums.get("REG0.SD").map(String::as_str), Some(option));
+            let mut packed = chip.tiles[0].clone();
+            packed.from_fasm(&mut db, &ft).unwrap();
+            assert!(packed.cram.delta(&tile.cram).is_empty());
+            assert_eq!(packed.decode_fasm(&mut db).enums, ft.enums);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -2,91 +2,151 @@ use crate::database::*;
 use crate::fasmparse::*;
 use crate::bels::*;
 use multimap::MultiMap;
-use std::collections::{BTreeMap, BTreeSet, HashMap};
+use num_bigint::BigInt as Integer;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
 use std::io::Write;
 
-use log::*;
-
 // 2D bit array
+// Each frame is stored as a row of 64-bit words, with unused bits at the end
+// of the row kept clear, so copies and comparisons can work a word at a time
 #[derive(Clone)]
 pub struct BitMatrix {
     pub frames: usize,
     pub bits: usize,
-    data: Vec<bool>,
+    words_per_frame: usize,
+    data: Vec<u64>,
+}
+
+// Read up to 64 bits from a row, starting at an arbitrary bit
+fn read_row_bits(row: &[u64], start: usize, len: usize) -> u64 {
+    let (w, o) = (start / 64, start % 64);
+    let mut val = row[w] >> o;
+    if o != 0 && o + len > 64 {
+        val |= row[w + 1] << (64 - o);
+    }
+    val & len_mask(len)
+}
+
+// Write up to 64 bits to a row, starting at an arbitrary bit
+fn write_row_bits(row: &mut [u64], start: usize, len: usize, val: u64) {
+    let (w, o) = (start / 64, start % 64);
+    let mask = len_mask(len);
+    let val = val & mask;
+    row[w] = (row[w] & !(mask << o)) | (val << o);
+    if o != 0 && o + len > 64 {
+        let hi_mask = mask >> (64 - o);
+        row[w + 1] = (row[w + 1] & !hi_mask) | (val >> (64 - o));
+    }
+}
+
+fn len_mask(len: usize) -> u64 {
+    if len >= 64 {
+        !0
+    } else {
+        (1 << len) - 1
+    }
+}
+
+// Copy a range of bits between rows
+fn copy_row_bits(to: &mut [u64], to_start: usize, from: &[u64], from_start: usize, len: usize) {
+    let mut i = 0;
+    while i < len {
+        let n = std::cmp::min(64, len - i);
+        write_row_bits(to, to_start + i, n, read_row_bits(from, from_start + i, n));
+        i += n;
+    }
 }
 
 impl BitMatrix {
     // Create new empty bitmatrix
     pub fn new(frames: usize, bits: usize) -> BitMatrix {
+        let words_per_frame = bits.div_ceil(64);
         BitMatrix {
             frames: frames,
             bits: bits,
-            data: vec![false; frames * bits],
+            words_per_frame,
+            data: vec![0; frames * words_per_frame],
         }
     }
+    fn row(&self, frame: usize) -> &[u64] {
+        &self.data[frame * self.words_per_frame..(frame + 1) * self.words_per_frame]
+    }
+    fn row_mut(&mut self, frame: usize) -> &mut [u64] {
+        &mut self.data[frame * self.words_per_frame..(frame + 1) * self.words_per_frame]
+    }
     // Getting and setting bits
+    #[inline]
     pub fn get(&self, frame: usize, bit: usize) -> bool {
-        self.data[frame * self.bits + bit]
+        debug_assert!(bit < self.bits);
+        (self.data[frame * self.words_per_frame + bit / 64] >> (bit % 64)) & 0x1 == 0x1
     }
+    #[inline]
     pub fn set(&mut self, frame: usize, bit: usize, val: bool) {
-        self.data[frame * self.bits + bit] = val
+        debug_assert!(bit < self.bits);
+        let word = &mut self.data[frame * self.words_per_frame + bit / 64];
+        if val {
+            *word |= 1 << (bit % 64);
+        } else {
+            *word &= !(1 << (bit % 64));
+        }
     }
     // Copy another bitmatrix to a window of this one
     pub fn copy_window(&mut self, from: &Self, start_frame: usize, start_bit: usize) {
+        assert!(start_bit + from.bits <= self.bits);
         for f in 0..from.frames {
-            for b in 0..from.bits {
-                self.data[(f + start_frame) * self.bits + (b + start_bit)] =
-                    from.data[f * from.bits + b];
-            }
+            copy_row_bits(self.row_mut(f + start_frame), start_bit, from.row(f), 0, from.bits);
         }
     }
     // Copy a window another bitmatrix  to this one
     pub fn copy_from_window(&mut self, from: &Self, start_frame: usize, start_bit: usize) {
+        assert!(start_bit + self.bits <= from.bits);
+        let bits = self.bits;
         for f in 0..self.frames {
-            for b in 0..self.bits {
-                self.data[f * self.bits + b] =
-                    from.data[(f + start_frame) * from.bits + (b + start_bit)];
+            copy_row_bits(self.row_mut(f), 0, from.row(f + start_frame), start_bit, bits);
+        }
+    }
+    // Call a function for each set bit of a list of words, in order
+    fn for_each_set(&self, data: impl Iterator<Item = u64>, mut func: impl FnMut(usize, usize)) {
+        for (i, mut w) in data.enumerate() {
+            let f = i / self.words_per_frame;
+            let base = (i % self.words_per_frame) * 64;
+            while w != 0 {
+                func(f, base + w.trailing_zeros() as usize);
+                w &= w - 1;
             }
         }
     }
     // Get a list of the differences
     // as a tuple (frame, bit, new value)
     pub fn delta(&self, base: &Self) -> Vec<(usize, usize, bool)> {
-        base.data
-            .iter()
-            .zip(self.data.iter())
-            .enumerate()
-            .filter_map(|(i, (o, n))| {
-                let f = i / self.bits;
-                let b = i % self.bits;
-                match (o, n) {
-                    (false, true) => Some((f, b, true)),  // going high
-                    (true, false) => Some((f, b, false)), // going low
-                    _ => None,
-                }
-            })
-            .collect()
+        let mut result = Vec::new();
+        let diff = base.data.iter().zip(self.data.iter()).map(|(o, n)| o ^ n);
+        self.for_each_set(diff, |f, b| result.push((f, b, self.get(f, b))));
+        result
     }
     // Pretty-print a list of frame-bits
     pub fn print(&self, mut out: &mut dyn Write) {
-        for (i, _x) in self.data.iter().enumerate().filter(|(_i, x)| **x) {
-            let f = i / self.bits;
-            let b = i % self.bits;
-            writeln!(&mut out, "F{}B{}", f, b).unwrap();
-        }
+        self.for_each_set(self.data.iter().copied(), |f, b| {
+            writeln!(&mut out, "F{}B{}", f, b).unwrap()
+        });
     }
     // Return true if any bit is set
     pub fn any(&self) -> bool {
-        return self.data.iter().any(|x| *x);
+        self.data.iter().any(|x| *x != 0)
+    }
+    // Number of set bits
+    pub fn count(&self) -> usize {
+        self.data.iter().map(|x| x.count_ones() as usize).sum()
     }
     // Get all set bits
     pub fn set_bits(&self) -> BTreeSet<(usize, usize)> {
-        self.data
-            .iter()
-            .enumerate()
-            .filter(|(_i, x)| **x)
-            .map(|(i, _x)| (i / self.bits, i % self.bits))
-            .collect()
+        let mut result = BTreeSet::new();
+        self.for_each_set(self.data.iter().copied(), |f, b| {
+            result.insert((f, b));
+        });
+        result
     }
 }
 
@@ -102,6 +162,9 @@ pub struct Chip {
     pub data: DeviceData,
     // Entire main bitstream content
     pub cram: BitMatrix,
+    // Bits that can change at runtime and are excluded from frame ECC
+    // (and should be skipped when comparing against readback)
+    pub ecc_mask: BitMatrix,
     // All of the tiles in the chip
     pub tiles: Vec<Tile>,
     // IP core and EBR configuration
@@ -115,6 +178,12 @@ pub struct Chip {
     pub metadata: Vec<String>,
     // Extra bitstream settings
     pub settings: BTreeMap<String, String>,
+    // USERCODE register value
+    pub usercode: u32,
+    // Control register 0 value (bits implied by settings are added on top)
+    pub ctrl0: u32,
+    // Power control register value
+    pub power_ctrl: u8,
     // number of TAP frames
     pub tap_frame_count: usize,
 }
@@ -131,6 +200,7 @@ impl Chip {
             variant: variant.to_string(),
             data: data.clone(),
             cram: BitMatrix::new(data.frames, data.bits_per_frame),
+            ecc_mask: BitMatrix::new(data.frames, data.bits_per_frame),
             tiles: tiles
                 .tiles
                 .iter()
@@ -142,6 +212,9 @@ impl Chip {
             tilegroups: HashMap::new(),
             metadata: Vec::new(),
             settings: BTreeMap::new(),
+            usercode: 0x00000000,
+            ctrl0: 0x00000000,
+            power_ctrl: 0x01,
             tap_frame_count: match device {
                 "LFCPNX-100" => 42,
                 _ => 24,
@@ -162,12 +235,9 @@ impl Chip {
         c
     }
     // Create a new chip from the database based on IDCODE or name
-    pub fn from_idcode(db: &mut Database, idcode: u32) -> Chip {
-        let (fam, device, variant, data) = db.device_by_idcode(idcode).expect(&format!(
-            "no device in database with IDCODE {:08x}\n",
-            idcode
-        ));
-        Chip::new(&fam, &device, &variant, &data, db.device_tilegrid(&fam, &device))
+    pub fn from_idcode(db: &mut Database, idcode: u32) -> Option<Chip> {
+        let (fam, device, variant, data) = db.device_by_idcode(idcode)?;
+        Some(Chip::new(&fam, &device, &variant, &data, db.device_tilegrid(&fam, &device)))
     }
     pub fn from_name(db: &mut Database, name: &str) -> Chip {
         let (fam, device, data) = db
@@ -181,7 +251,14 @@ impl Chip {
             .expect(&format!("no device in database with name {}\n", name));
         Chip::new(&fam, &device, variant, &data, db.device_tilegrid(&fam, &device))
     }
-    pub fn from_fasm(db: &mut Database, fasm: &ParsedFasm, device: Option<&str>) -> Chip {
+    // Build a chip from FASM, stopping at the first feature that can't be applied
+    // unless keep_going is set, in which case an error is collected for every one
+    pub fn from_fasm(
+        db: &mut Database,
+        fasm: &ParsedFasm,
+        device: Option<&str>,
+        keep_going: bool,
+    ) -> Result<Chip, Vec<FasmError>> {
         let mut chip = match device {
             Some(d) => Chip::from_name(db, d),
             None => {
@@ -189,7 +266,7 @@ impl Chip {
                     .attrs
                     .iter()
                     .find(|(k, _)| k == "oxide.device")
-                    .unwrap()
+                    .ok_or_else(|| vec![FasmError::new(0, "no oxide.device attribute".to_string())])?
                     .1;
                 let default_variant = ("".to_string(), "".to_string());
                 let variant = &fasm
@@ -201,6 +278,7 @@ impl Chip {
                 Chip::from_name_variant(db, name, variant)
             }
         };
+        let mut errors = Vec::new();
         chip.create_tilegroups(db);
         chip.metadata.extend(
             fasm.attrs
@@ -208,6 +286,43 @@ impl Chip {
                 .filter_map(|(k, v)| if k == "oxide.meta" { Some(v) } else { None })
                 .cloned(),
         );
+        for (k, v) in fasm.attrs.iter() {
+            let line = *fasm.attr_lines.get(k).unwrap_or(&0);
+            let int_value = || {
+                parse_attr_int(v).ok_or_else(|| {
+                    FasmError::new(line, format!("invalid value '{}' for attribute {}", v, k))
+                })
+            };
+            let out_of_range = |x: u64| {
+                FasmError::new(line, format!("value 0x{:X} out of range for attribute {}", x, k))
+            };
+            let result = match k.as_str() {
+                "oxide.usercode" => int_value()
+                    .and_then(|x| u32::try_from(x).map_err(|_| out_of_range(x)))
+                    .map(|x| chip.usercode = x),
+                "oxide.ctrl0" => int_value()
+                    .and_then(|x| u32::try_from(x).map_err(|_| out_of_range(x)))
+                    .map(|x| chip.ctrl0 = x),
+                "oxide.power_ctrl" => int_value()
+                    .and_then(|x| u8::try_from(x).map_err(|_| out_of_range(x)))
+                    .map(|x| chip.power_ctrl = x),
+                // The SED CRC is not confirmed against hardware, so is only written
+                // with the experimental pack option
+                "oxide.sed_crc" => Err(FasmError::new(
+                    line,
+                    "oxide.sed_crc is experimental, use pack --experimental-sed-crc instead".to_string(),
+                )),
+                "oxide.secure" => {
+                    chip.settings.insert("secure".to_string(), v.to_string());
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+            errors.extend(result.err());
+            if !keep_going && !errors.is_empty() {
+                return Err(errors);
+            }
+        }
         for t in chip.tiles.iter_mut() {
             let tdb = db.tile_bitdb(&chip.family, &t.tiletype);
             for aon in tdb.db.always_on.iter() {
@@ -216,18 +331,34 @@ impl Chip {
         }
         for (tn, ft) in fasm.tiles.iter() {
             // Might be a tilegroup or single tile
-            if tn.starts_with("IP_") {
+            let result = if let Some(ip_name) = tn.strip_prefix("IP_") {
                 // IP configuration space
-                let ip_name = &tn[3..];
-                chip.configure_ip(ip_name, db, ft);
+                chip.configure_ip(ip_name, db, ft)
             } else if chip.tilegroups.contains_key(tn) {
-                chip.apply_tilegroup(tn, db, ft);
+                chip.apply_tilegroup(tn, db, ft)
             } else {
-                chip.tile_by_name_mut(tn).unwrap().from_fasm(db, ft);
+                match chip.tile_by_name_mut(tn) {
+                    Ok(t) => t.from_fasm(db, ft),
+                    Err(e) => Err(vec![FasmError::new(ft.line, e)]),
+                }
+            };
+            if let Err(e) = result {
+                errors.extend(e);
+                if !keep_going {
+                    break;
+                }
             }
         }
+        if !errors.is_empty() {
+            errors.sort_by_key(|e| e.line);
+            if !keep_going {
+                errors.truncate(1);
+            }
+            return Err(errors);
+        }
         chip.tiles_to_cram();
-        return chip;
+        chip.update_ecc_mask(db);
+        Ok(chip)
     }
     // Copy the whole-chip CRAM to the per-tile CRAM
     pub fn cram_to_tiles(&mut self) {
@@ -242,26 +373,48 @@ impl Chip {
             self.cram.copy_window(&t.cram, t.start_frame, t.start_bit);
         }
     }
+    // Recompute the ECC mask from the dynamic words of each tile,
+    // based on the current whole-chip CRAM
+    pub fn update_ecc_mask(&mut self, db: &mut Database) {
+        let mut mask = BitMatrix::new(self.cram.frames, self.cram.bits);
+        for t in self.tiles.iter() {
+            let tdb = db.tile_bitdb(&self.family, &t.tiletype);
+            let get_bit = |cb: &ConfigBit| self.cram.get(t.start_frame + cb.frame, t.start_bit + cb.bit);
+            for (word, dd) in tdb.db.dynamic.iter() {
+                if !dd.enum_name.is_empty() {
+                    let curr_opt = tdb.db.enums.get(&dd.enum_name).map(|edata| decode_enum(edata, get_bit, tdb.shared_bits()));
+                    match curr_opt {
+                        Some(OptionMatch::Exact(opt)) if dd.options.contains(opt) => {}
+                        _ => continue,
+                    }
+                }
+                if let Some(wdata) = tdb.db.words.get(word) {
+                    for cb in wdata.bits.iter().flatten() {
+                        mask.set(t.start_frame + cb.frame, t.start_bit + cb.bit, true);
+                    }
+                }
+            }
+        }
+        self.ecc_mask = mask;
+    }
     // Get a tile by name
-    pub fn tile_by_name(&self, name: &str) -> Result<&Tile, &'static str> {
+    pub fn tile_by_name(&self, name: &str) -> Result<&Tile, String> {
         match self.tiles_by_name.get(name) {
-            None => {
-                println!("no tile named {}", name);
-                Err("unknown tile name")
-            }
+            None => Err(format!("no tile named {}", name)),
             Some(i) => Ok(&self.tiles[*i]),
         }
     }
     // Get a mutable tile by name
-    pub fn tile_by_name_mut(&mut self, name: &str) -> Result<&mut Tile, &'static str> {
+    pub fn tile_by_name_mut(&mut self, name: &str) -> Result<&mut Tile, String> {
         match self.tiles_by_name.get(name) {
-            None => {
-                println!("no tile named {}", name);
-                Err("unknown tile name")
-            }
+            None => Err(format!("no tile named {}", name)),
             Some(i) => Ok(&mut self.tiles[*i]),
         }
     }
+    // Get all bits set to different values by two FASM features
+    pub fn bit_conflicts(&self) -> Vec<&BitConflict> {
+        self.tiles.iter().flat_map(|t| t.conflicts.iter()).collect()
+    }
     // Get all tiles at a location
     pub fn tiles_by_xy(&self, x: u32, y: u32) -> Vec<&Tile> {
         match self.tiles_by_loc.get_vec(&(x, y)) {
@@ -321,54 +474,61 @@ impl Chip {
         }
     }
     // Convert frame address to flat frame index
-    pub fn frame_addr_to_idx(&self, addr: u32) -> usize {
+    // Returns None for addresses outside the device
+    pub fn frame_addr_to_idx(&self, addr: u32) -> Option<usize> {
         match addr {
-            0x0000..=0x7FFF => (self.cram.frames - 1) - (addr as usize),
-            0x8000..=0x800F => (15 - ((addr - 0x8000) as usize)) + (16 + self.tap_frame_count), // right side IO
-            0x8010..=0x801F => (15 - ((addr - 0x8010) as usize)) + 0,  // left side IO
-            0x8020..=0x81FF => ((self.tap_frame_count - 1) - ((addr - 0x8020) as usize)) + 16, // TAPs (row-segment clocking)
-            _ => panic!("unable to process frame address 0x{:08x}", addr),
+            0x0000..=0x7FFF => (self.cram.frames - 1).checked_sub(addr as usize)
+                .filter(|&idx| idx >= 32 + self.tap_frame_count),
+            0x8000..=0x800F => Some((15 - ((addr - 0x8000) as usize)) + (16 + self.tap_frame_count)), // right side IO
+            0x8010..=0x801F => Some(15 - ((addr - 0x8010) as usize)),  // left side IO
+            0x8020..=0x81FF => self.tap_frame_count.checked_sub(1 + (addr - 0x8020) as usize)
+                .map(|idx| idx + 16), // TAPs (row-segment clocking)
+            _ => None,
         }
     }
     // Get the frame size in bytes for bus regions
-    pub fn get_bus_frame_size(&self, addr: u32) -> usize {
+    pub fn get_bus_frame_size(&self, addr: u32) -> Option<usize> {
         match (addr & 0xF0000000) >> 28 {
-            0 => 1, // non-PCIe IP cores
-            2 => 5, // BRAM and LRAM
-            3 => 4, // PCIe IP
-            _ => panic!(
-                "unable to determine frame size of bus address 0x{:08x}",
-                addr
-            ),
+            0 => Some(1), // non-PCIe IP cores
+            2 => Some(5), // BRAM and LRAM
+            3 => Some(4), // PCIe IP
+            _ => None,
         }
     }
     // Convert a long package name to a short one
-    pub fn get_package_short_name(long_name: &str) -> String {
+    pub fn get_package_short_name(long_name: &str) -> Result<String, String> {
         if long_name.starts_with("CABGA") {
-            format!("BG{}", &long_name[5..])
+            Ok(format!("BG{}", &long_name[5..]))
         } else if long_name.starts_with("CSBGA") {
-            format!("MG{}", &long_name[5..])
+            Ok(format!("MG{}", &long_name[5..]))
         } else if long_name.starts_with("CSFBGA") {
-            format!("MG{}", &long_name[6..])
+            Ok(format!("MG{}", &long_name[6..]))
         } else if long_name.starts_with("QFN") {
-            format!("SG{}", &long_name[3..])
+            Ok(format!("SG{}", &long_name[3..]))
         } else if long_name.starts_with("WLCSP") {
-            format!("UWG{}", &long_name[5..])
+            Ok(format!("UWG{}", &long_name[5..]))
         } else {
-            panic!("unknown package name {}", &long_name);
+            Err(format!("unknown package name {}", long_name))
         }
     }
     // Get the base address for an IP
-    pub fn get_ip_baseaddr(&self, db: &mut Database, ip: &str) -> u32 {
+    pub fn get_ip_baseaddr(&self, db: &mut Database, ip: &str) -> Result<u32, String> {
         let baseaddrs = db.device_baseaddrs(&self.family, &self.device);
-        if ip.starts_with("EBR_WID") {
+        let region_addr = |name: &str| {
+            baseaddrs
+                .regions
+                .get(name)
+                .map(|r| r.addr)
+                .ok_or(format!("no IP named {} for {}", name, self.device))
+        };
+        if let Some(wid) = ip.strip_prefix("EBR_WID") {
             // Special case as we don't want to fill up the DB with 2048 entries
-            let base = baseaddrs.regions.get("EBR_WID0").unwrap().addr;
-            let offset = baseaddrs.regions.get("EBR_WID1").unwrap().addr - base;
-            let wid = ip[7..].parse::<u32>().unwrap();
-            return base + wid * offset;
+            let base = region_addr("EBR_WID0")?;
+            let offset = region_addr("EBR_WID1")? - base;
+            let wid = wid.parse::<u32>().map_err(|_| format!("no IP named {}", ip))?;
+            Ok(base + wid * offset)
         } else {
-            return baseaddrs.regions.get(ip).unwrap_or_else(|| panic!("no IP named {}", ip)).addr;
+            region_addr(ip)
         }
     }
     // Sets an IP bit
@@ -398,9 +558,17 @@ impl Chip {
     }
     // Apply a tilegroup to all tiles within it
     // This sets applicable words and enums to all tiles that match inside the tilegroup
-    pub fn apply_tilegroup(&mut self, group: &str, db: &mut Database, ft: &FasmTile) {
-        let tg = self.tilegroups.get(group).unwrap_or_else(|| panic!("No tilegroup named {}", group)).clone();
+    pub fn apply_tilegroup(
+        &mut self,
+        group: &str,
+        db: &mut Database,
+        ft: &FasmTile,
+    ) -> Result<(), Vec<FasmError>> {
+        let tg = self.tilegroups.get(group).ok_or_else(|| {
+            vec![FasmError::new(ft.line, format!("no tilegroup named {}", group))]
+        })?.clone();
         let tdbs : Vec<TileBitsDatabase> = tg.iter().map(|x| db.tile_bitdb(&self.family, &self.tile_by_name(x).unwrap().tiletype).db.clone()).collect();
+        let mut errors = Vec::new();
         for i in 0..2 {
             // Process "BASE_" enums first
             for (k, v) in ft
@@ -410,25 +578,27 @@ impl Chip {
             {
                 let mut found = false;
                 for (tile, tdb) in tg.iter().zip(tdbs.iter()) {
-                    match tdb.enums.get(k) {
-                        Some(en) => {
-                            let opt = en.options.get(v).unwrap_or_else(|| panic!("No option named {} for enum {} in tile {}.\n\
-Valid options are: {}\n\
-Please make sure Oxide and nextpnr are up to date and input source code is meaningful. If they are, consider reporting this as an issue.",
-                                        v, k, &tile,
-                                        en.options.keys().cloned().collect::<Vec<String>>().join(", ")));
-                            let tiledata = self.tile_by_name_mut(&tile).unwrap();
-                            for bit in opt.iter() {
-                                tiledata.cram.set(bit.frame, bit.bit, !bit.invert);
+                    if let Some(en) = tdb.enums.get(k) {
+                        found = true;
+                        let opt = match en.options.get(v) {
+                            Some(opt) => opt,
+                            None => {
+                                errors.push(unknown_option_error(ft, k, v, &format!("tile {}", tile), en));
+                                continue;
                             }
-                            found = true;
+                        };
+                        let tiledata = self.tile_by_name_mut(tile).unwrap();
+                        let owner = tiledata.add_feature(BitOwner::new(format!("{}.{}.{}", group, k, v), ft.line_of(k), k));
+                        for bit in opt.iter() {
+                            tiledata.set_feature_bit(bit.frame, bit.bit, !bit.invert, owner);
                         }
-                        None => {}
                     }
                 }
                 if !found {
-                    panic!("No enum named {} in tilegroup {}.\n\
-Please make sure Oxide and nextpnr are up to date. If they are, consider reporting this as an issue.", k, group);
+                    errors.push(FasmError::new(
+                        ft.line_of(k),
+                        format!("no enum named {} in tilegroup {}", k, group),
+                    ));
                 }
             }
         }
@@ -436,82 +606,243 @@ Please make sure Oxide and nextpnr are up to date. If they are, consider reporti
         for (k, v) in ft.words.iter() {
             let mut found = false;
             for (tile, tdb) in tg.iter().zip(tdbs.iter()) {
-                match tdb.words.get(k) {
-                    Some(w) => {
-                        if (v.bits() as usize) > w.bits.len() {
-                            panic!(
-                                "Word {} in tile {} has value width {} exceeding database width of {}",
-                                k,
-                                &tile,
-                                v.bits(),
-                                w.bits.len()
-                            );
-                        }
-                        let tiledata = self.tile_by_name_mut(&tile).unwrap();
-                        for (i, wb) in w.bits.iter().enumerate() {
-                            let bit_val = v.bit(i as u64);
-                            for bit in wb {
-                                tiledata.cram.set(bit.frame, bit.bit, bit.invert != bit_val);
-                            }
+                if let Some(w) = tdb.words.get(k) {
+                    found = true;
+                    if (v.bits() as usize) > w.bits.len() {
+                        errors.push(word_width_error(ft, k, &format!("tile {}", tile), v.bits(), w));
+                        continue;
+                    }
+                    let tiledata = self.tile_by_name_mut(tile).unwrap();
+                    let owner = tiledata.add_feature(BitOwner::new(format!("{}.{}", group, k), ft.line_of(k), k));
+                    for (i, wb) in w.bits.iter().enumerate() {
+                        let bit_val = v.bit(i as u64);
+                        for bit in wb {
+                            tiledata.set_feature_bit(bit.frame, bit.bit, bit.invert != bit_val, owner);
                         }
-                        found = true;
                     }
-                    None => {}
                 }
             }
             if !found {
-                panic!("No word named {} in tilegroup {}.\n\
-Please make sure Oxide and nextpnr are up to date. If they are, consider reporting this as an issue.", k, group);
+                errors.push(FasmError::new(
+                    ft.line_of(k),
+                    format!("no word named {} in tilegroup {}", k, group),
+                ));
             }
         }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
     }
     // Go from IP name to IP type
-    pub fn get_ip_type(&self, ip: &str) -> &'static str {
+    pub fn get_ip_type(&self, ip: &str) -> Result<&'static str, String> {
+        Chip::ip_type_by_name(ip).ok_or_else(|| format!("no IP data for {}", ip))
+    }
+    // Go from IP name to IP type, returning None for IP without a database
+    pub fn ip_type_by_name(ip: &str) -> Option<&'static str> {
         if ip.starts_with("EBR_WID") {
-            return "EBR_INIT";
+            Some("EBR_INIT")
         } else if ip.starts_with("PLL_") {
-            return "PLL_CORE";
+            Some("PLL_CORE")
         } else if ip.starts_with("TDPHY_") {
-            return "DPHY_CORE";
+            Some("DPHY_CORE")
         } else if ip.starts_with("TPCIE_") {
-            return "PCIE_CORE";
+            Some("PCIE_CORE")
         } else if ip.starts_with("LRAM_") {
-            return "LRAM_INIT";
+            Some("LRAM_INIT")
         } else {
-            panic!("no IP data for {}", ip);
+            None
+        }
+    }
+    // Decode the IP configuration into FASM features, by IP_ prefixed name, for IP with a
+    // bit database. Written bytes that the decoded settings wouldn't reproduce exactly
+    // are given as words of IP_UNKNOWN.
+    pub fn decode_ip_fasm(&self, db: &mut Database) -> BTreeMap<String, FasmTile> {
+        let regions: Vec<(String, &'static str, u32, u32)> = db
+            .device_baseaddrs(&self.family, &self.device)
+            .regions
+            .iter()
+            .filter_map(|(name, r)| match Chip::ip_type_by_name(name) {
+                Some(t @ "PLL_CORE") | Some(t @ "DPHY_CORE") | Some(t @ "PCIE_CORE") => {
+                    Some((name.to_string(), t, r.addr, r.addr + (1 << r.abits)))
+                }
+                _ => None,
+            })
+            .collect();
+        let mut result = BTreeMap::new();
+        // Bytes as they would be written by configure_ip for the decoded settings
+        let mut rewritten = BTreeMap::<u32, u8>::new();
+        for (ip, iptype, start, end) in regions.iter() {
+            if self.ipconfig.range(*start..*end).next().is_none() {
+                continue;
+            }
+            let tdb_data = db.ip_bitdb(&self.family, iptype);
+            let (tdb, shared) = (&tdb_data.db, tdb_data.shared_bits());
+            let get_bit = |cb: &ConfigBit| {
+                self.ipconfig
+                    .get(&(start + cb.frame as u32))
+                    .is_some_and(|b| (b >> cb.bit) & 0x1 == 0x1)
+            };
+            let mut ft = FasmTile::new();
+            let mut ft_bits = BTreeMap::<u32, u8>::new();
+            let mut rewrite = |addr: u32, bit: usize, value: bool| {
+                let byte = ft_bits.entry(addr).or_insert(0);
+                if value {
+                    *byte |= 1 << bit;
+                } else {
+                    *byte &= !(1 << bit);
+                }
+            };
+            let fasm_name = format!("IP_{}", ip);
+            for (name, edata) in tdb.enums.iter() {
+                let m = decode_enum(edata, get_bit, shared);
+                if let Some(opt) = decode_match(&mut ft, &fasm_name, name, m) {
+                    ft.enums.insert(name.to_string(), opt.to_string());
+                    for cb in edata.options[opt].iter() {
+                        rewrite(start + cb.frame as u32, cb.bit, !cb.invert);
+                    }
+                }
+            }
+            let written = |cb: &ConfigBit| self.ipconfig.contains_key(&(start + cb.frame as u32));
+            for (name, wdata) in tdb.words.iter() {
+                // Skip words that weren't written, or were left at their default value
+                if !wdata.bits.iter().flatten().all(written)
+                    || !wdata.bits.iter().flatten().any(|cb| get_bit(cb) != cb.invert)
+                {
+                    continue;
+                }
+                let mut value = Integer::from(0);
+                for (i, b) in wdata.bits.iter().enumerate() {
+                    let bit_val = b.iter().all(|cb| get_bit(cb) != cb.invert);
+                    value.set_bit(i as u64, bit_val);
+                    for cb in b.iter() {
+                        rewrite(start + cb.frame as u32, cb.bit, cb.invert != bit_val);
+                    }
+                }
+                ft.words.insert(name.to_string(), value);
+            }
+            if !ft.enums.is_empty() || !ft.words.is_empty() || !ft.warnings.is_empty() {
+                // Special PLL enable/update bit, set by configure_ip for any PLL setting
+                if iptype == &"PLL_CORE" {
+                    rewrite(*start, 0, true);
+                }
+                rewritten.extend(ft_bits);
+                result.insert(fasm_name, ft);
+            }
+        }
+        // Any byte that the decoded settings would not rewrite exactly, including bytes
+        // written as zero, must be kept. IP_UNKNOWN is applied last and overrides them.
+        let mut unknown = FasmTile::new();
+        for (addr, val) in self.ipconfig.iter() {
+            if rewritten.get(addr) == Some(val) {
+                continue;
+            }
+            unknown.words.insert(format!("0x{:08X}", addr), Integer::from(*val));
         }
+        if !unknown.words.is_empty() {
+            result.insert("IP_UNKNOWN".to_string(), unknown);
+        }
+        result
+    }
+    // Write the IP configuration as FASM, decoding IP with a bit database back
+    // to named settings. Bytes that can't be fully explained are written as IP_UNKNOWN.
+    // Returns any warnings from decoding.
+    pub fn write_ip_fasm(&self, db: &mut Database, mut out: &mut dyn Write) -> Vec<String> {
+        let decoded = self.decode_ip_fasm(db);
+        let mut warnings = Vec::new();
+        for (fasm_name, ft) in decoded.iter().filter(|(k, _v)| *k != "IP_UNKNOWN") {
+            warnings.extend(ft.warnings.iter().cloned());
+            if ft.enums.is_empty() && ft.words.is_empty() {
+                continue;
+            }
+            let iptype = match self.get_ip_type(&fasm_name[3..]) {
+                Ok(t) => t,
+                Err(e) => {
+                    warnings.push(e);
+                    continue;
+                }
+            };
+            let tdb = &db.ip_bitdb(&self.family, iptype).db;
+            for (name, opt) in ft.enums.iter() {
+                writeln!(&mut out, "{}.{}.{}", fasm_name, name, opt).unwrap();
+            }
+            for (name, value) in ft.words.iter() {
+                let width = tdb.words[name].bits.len();
+                writeln!(
+                    &mut out,
+                    "{}.{}[{}:0] = {}'b{}",
+                    fasm_name,
+                    name,
+                    width - 1,
+                    width,
+                    word_bitstr(value, width)
+                )
+                .unwrap();
+            }
+            writeln!(&mut out).unwrap();
+        }
+        if let Some(unknown) = decoded.get("IP_UNKNOWN") {
+            for (addr, val) in unknown.words.iter() {
+                writeln!(&mut out, "IP_UNKNOWN.{}[7:0] = 8'h{:02X};", addr, val).unwrap();
+            }
+        }
+        warnings
     }
     // Configure an IP
-    pub fn configure_ip(&mut self, ip: &str, db: &mut Database, ft: &FasmTile) {
+    pub fn configure_ip(&mut self, ip: &str, db: &mut Database, ft: &FasmTile) -> Result<(), Vec<FasmError>> {
+        let mut errors = Vec::new();
         // This is a special tile for currently-unknown IP bits
         if ip == "UNKNOWN" {
             for (k, v) in ft.words.iter() {
-                assert!(&k[0..2] == "0x");
-                let addr = u32::from_str_radix(&k[2..], 16).unwrap();
+                let addr = match k.strip_prefix("0x").and_then(|a| u32::from_str_radix(a, 16).ok()) {
+                    Some(addr) => addr,
+                    None => {
+                        errors.push(FasmError::new(ft.line_of(k), format!("invalid IP_UNKNOWN address {}", k)));
+                        continue;
+                    }
+                };
                 for i in 0..8 {
                     let bit_val = v.bit(i as u64);
                     self.set_ip_bit(0x0, addr, i, bit_val);
                 }
             }
         } else {
-            let baseaddr = self.get_ip_baseaddr(db, ip);
-            let tdb = &db.ip_bitdb(&self.family, self.get_ip_type(ip)).db;
+            let known_ip = Chip::ip_type_by_name(ip).is_some()
+                && (ip.starts_with("EBR_WID") && ip[7..].parse::<u32>().is_ok()
+                    || db.device_baseaddrs(&self.family, &self.device).regions.contains_key(ip));
+            if !known_ip {
+                return Err(vec![FasmError::new(ft.line, format!("no IP named {}", ip))]);
+            }
+            let baseaddr = match self.get_ip_baseaddr(db, ip) {
+                Ok(addr) => addr,
+                Err(e) => return Err(vec![FasmError::new(ft.line, e)]),
+            };
+            let iptype = self.get_ip_type(ip).map_err(|e| vec![FasmError::new(ft.line, e)])?;
+            let tdb = &db.ip_bitdb(&self.family, iptype).db;
             // Special PLL enable/update bit
             if ip.starts_with("PLL_") {
                 self.set_ip_bit(baseaddr, 0, 0, true);
             } else if ip.starts_with("LRAM_") {
                 // In order to avoid a multi-megabyte file and slow DB loads, we define most of the LRAM initialisation programmatically
-                assert!(ft.enums.is_empty());
+                for k in ft.enums.keys() {
+                    errors.push(FasmError::new(ft.line_of(k), format!("unexpected enum {} in IP {}", k, ip)));
+                }
                 // Full set of zeros is needed
                 for i in 0..81920 {
                     self.set_ip_bit(baseaddr, i, 0, false);
                 }
 
                 for (k, v) in ft.words.iter() {
-                    assert!(k.starts_with("INITVAL_"));
-                    let init_word: u32 = u32::from_str_radix(&k[8..], 16).unwrap();
+                    let init_word = match k.strip_prefix("INITVAL_").and_then(|w| u32::from_str_radix(w, 16).ok()) {
+                        Some(w) => w,
+                        None => {
+                            errors.push(FasmError::new(ft.line_of(k), format!("no word named {} in IP {}", k, ip)));
+                            continue;
+                        }
+                    };
                     let offset = 0x280 * init_word;
                     let  w = tdb.words.get("INITVAL_00").unwrap();
+                    if (v.bits() as usize) > w.bits.len() {
+                        errors.push(word_width_error(ft, k, &format!("IP {}", ip), v.bits(), w));
+                        continue;
+                    }
                     for (i, wb) in w.bits.iter().enumerate() {
                         let bit_val = v.bit(i as u64);
                         for bit in wb {
@@ -519,36 +850,43 @@ Please make sure Oxide and nextpnr are up to date. If they are, consider reporti
                         }
                     }
                 }
-                return;
+                return if errors.is_empty() { Ok(()) } else { Err(errors) };
             }
             // Enums
             for (k, v) in ft
                 .enums
                 .iter()
             {
-                let en = tdb.enums.get(k).unwrap_or_else(|| panic!("No enum named {} in IP {}.\n\
-    Please make sure Oxide and nextpnr are up to date. If they are, consider reporting this as an issue.", k, ip));
-                let opt = en.options.get(v).unwrap_or_else(|| panic!("No option named {} for enum {} in tile {}.\n\
-    Valid options are: {}\n\
-    Please make sure Oxide and nextpnr are up to date and input source code is meaningful. If they are, consider reporting this as an issue.",
-                            v, k, ip,
-                            en.options.keys().cloned().collect::<Vec<String>>().join(", ")));
+                let en = match tdb.enums.get(k) {
+                    Some(en) => en,
+                    None => {
+                        errors.push(FasmError::new(ft.line_of(k), format!("no enum named {} in IP {}", k, ip)));
+                        continue;
+                    }
+                };
+                let opt = match en.options.get(v) {
+                    Some(opt) => opt,
+                    None => {
+                        errors.push(unknown_option_error(ft, k, v, &format!("IP {}", ip), en));
+                        continue;
+                    }
+                };
                 for bit in opt.iter() {
                     self.set_ip_bit(baseaddr, bit.frame as u32, bit.bit as u32, !bit.invert);
                 }
             }
             // Words
             for (k, v) in ft.words.iter() {
-                let w = tdb.words.get(k).unwrap_or_else(|| panic!("No word named {} in IP {}.\n\
-    Please make sure Oxide and nextpnr are up to date. If they are, consider reporting this as an issue.", k, ip));
+                let w = match tdb.words.get(k) {
+                    Some(w) => w,
+                    None => {
+                        errors.push(FasmError::new(ft.line_of(k), format!("no word named {} in IP {}", k, ip)));
+                        continue;
+                    }
+                };
                 if (v.bits() as usize) > w.bits.len() {
-                    panic!(
-                        "Word {} in IP {} has value width {} exceeding database width of {}",
-                        k,
-                        ip,
-                        v.bits(),
-                        w.bits.len()
-                    );
+                    errors.push(word_width_error(ft, k, &format!("IP {}", ip), v.bits(), w));
+                    continue;
                 }
                 for (i, wb) in w.bits.iter().enumerate() {
                     let bit_val = v.bit(i as u64);
@@ -558,6 +896,7 @@ Please make sure Oxide and nextpnr are up to date. If they are, consider reporti
                 }
             }
         }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
     }
     // Lookup idcode
     pub fn get_idcode(&self) -> u32 {
@@ -566,6 +905,195 @@ Please make sure Oxide and nextpnr are up to date. If they are, consider reporti
     }
 }
 
+// Format a word value as a binary string of a given width, MSB first
+fn word_bitstr(value: &Integer, width: usize) -> String {
+    (0..width)
+        .rev()
+        .map(|i| if value.bit(i as u64) { '1' } else { '0' })
+        .collect()
+}
+
+// Error for an enum option missing from the database
+fn unknown_option_error(ft: &FasmTile, k: &str, v: &str, loc: &str, en: &ConfigEnumData) -> FasmError {
+    FasmError::new(
+        ft.line_of(k),
+        format!(
+            "no option named {} for enum {} in {}, valid options are: {}",
+            v,
+            k,
+            loc,
+            en.options.keys().cloned().collect::<Vec<String>>().join(", ")
+        ),
+    )
+}
+
+// Error for a word value wider than the database word
+fn word_width_error(ft: &FasmTile, k: &str, loc: &str, width: u64, w: &ConfigWordData) -> FasmError {
+    FasmError::new(
+        ft.line_of(k),
+        format!(
+            "word {} in {} has value width {} exceeding database width of {}",
+            k,
+            loc,
+            width,
+            w.bits.len()
+        ),
+    )
+}
+
+// A FASM feature that sets CRAM bits
+#[derive(Clone, Debug, PartialEq)]
+pub struct BitOwner {
+    pub feature: String,
+    pub line: usize,
+    // Set by a BASE_ enum, so may be overridden
+    pub base: bool,
+}
+
+impl BitOwner {
+    pub fn new(feature: String, line: usize, key: &str) -> BitOwner {
+        BitOwner {
+            feature,
+            line,
+            base: key.starts_with("BASE_"),
+        }
+    }
+}
+
+impl fmt::Display for BitOwner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (line {})", self.feature, self.line)
+    }
+}
+
+// Two FASM features that disagree on the value of a CRAM bit, given both
+// relative to the tile and in the whole-chip CRAM
+#[derive(Clone, Debug, PartialEq)]
+pub struct BitConflict {
+    pub tile: String,
+    pub frame: usize,
+    pub bit: usize,
+    pub chip_frame: usize,
+    pub chip_bit: usize,
+    pub first: BitOwner,
+    pub second: BitOwner,
+    // Value set by the second feature
+    pub value: bool,
+}
+
+impl fmt::Display for BitConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "conflict at {} F{}B{} (chip F{}B{}): {} sets {}, but {} sets {}",
+            self.tile,
+            self.frame,
+            self.bit,
+            self.chip_frame,
+            self.chip_bit,
+            self.first,
+            !self.value as u8,
+            self.second,
+            self.value as u8
+        )
+    }
+}
+
+// Result of matching the options of an enum or pip mux against the CRAM
+#[derive(Debug, PartialEq)]
+pub enum OptionMatch<'a> {
+    // Bits are as left when no option is written
+    Default,
+    Exact(&'a str),
+    // More than one option exactly matches
+    Ambiguous(Vec<&'a str>),
+    // No option exactly matches; these options have all of their own bits matching
+    Partial(Vec<&'a str>),
+}
+
+// Match the options of an enum or pip mux as an exact cover of its bits.
+// The CRAM starts cleared and only the bits of the chosen option are written, so
+// an option matches exactly if its own bits have the values it writes and the
+// other bits of the enum or mux are cleared. Cleared bits match the default, unless
+// the declared default option sets bits; then they match the options that only
+// clear bits, which must have been chosen instead.
+// Bits shared with other features are only checked by the options that contain them
+pub fn match_options<'a>(
+    options: &[(&'a str, &'a BTreeSet<ConfigBit>)],
+    default: Option<&str>,
+    get_bit: impl Fn(&ConfigBit) -> bool,
+    shared: &HashSet<(usize, usize)>,
+) -> OptionMatch<'a> {
+    let all_bits: BTreeMap<(usize, usize), &ConfigBit> = options
+        .iter()
+        .flat_map(|(_k, bits)| bits.iter())
+        .map(|cb| ((cb.frame, cb.bit), cb))
+        .collect();
+    let cleared_except = |bits: &BTreeSet<ConfigBit>| {
+        all_bits.iter().all(|(loc, cb)| {
+            shared.contains(loc) || bits.iter().any(|b| (b.frame, b.bit) == *loc) || !get_bit(cb)
+        })
+    };
+    let candidates: Vec<&(&str, &BTreeSet<ConfigBit>)> = options
+        .iter()
+        .filter(|(_k, bits)| !bits.is_empty() && bits.iter().all(|cb| get_bit(cb) != cb.invert))
+        .collect();
+    let exact: Vec<&str> = candidates
+        .iter()
+        .filter(|(_k, bits)| cleared_except(bits))
+        .map(|(k, _bits)| *k)
+        .collect();
+    if cleared_except(&BTreeSet::new()) {
+        let default_sets_bits = options
+            .iter()
+            .find(|(k, _bits)| Some(*k) == default)
+            .is_some_and(|(_k, bits)| bits.iter().any(|cb| !cb.invert));
+        if !default_sets_bits {
+            return OptionMatch::Default;
+        }
+        let cleared: Vec<&str> = options
+            .iter()
+            .filter(|(_k, bits)| bits.iter().all(|cb| get_bit(cb) != cb.invert))
+            .map(|(k, _bits)| *k)
+            .collect();
+        return match cleared.len() {
+            0 => OptionMatch::Default,
+            1 => OptionMatch::Exact(cleared[0]),
+            _ => OptionMatch::Ambiguous(cleared),
+        };
+    }
+    match exact.len() {
+        1 => OptionMatch::Exact(exact[0]),
+        0 => OptionMatch::Partial(candidates.iter().map(|(k, _bits)| *k).collect()),
+        _ => OptionMatch::Ambiguous(exact),
+    }
+}
+
+// Decode the option of an enum from the CRAM
+pub fn decode_enum<'a>(
+    edata: &'a ConfigEnumData,
+    get_bit: impl Fn(&ConfigBit) -> bool,
+    shared: &HashSet<(usize, usize)>,
+) -> OptionMatch<'a> {
+    let options: Vec<(&str, &BTreeSet<ConfigBit>)> =
+        edata.options.iter().map(|(k, v)| (k.as_str(), v)).collect();
+    match_options(&options, edata.default.as_deref(), get_bit, shared)
+}
+
+// Get the matched option of an enum or pip mux. Ambiguous and partial matches are
+// added to the warnings of the decoded tile, and no option is returned
+fn decode_match<'a>(ft: &mut FasmTile, fasm_name: &str, feature: &str, m: OptionMatch<'a>) -> Option<&'a str> {
+    let warning = match m {
+        OptionMatch::Default => return None,
+        OptionMatch::Exact(opt) => return Some(opt),
+        OptionMatch::Ambiguous(opts) => format!("is ambiguous, could be any of {}", opts.join(", ")),
+        OptionMatch::Partial(opts) if opts.is_empty() => "matches no option".to_string(),
+        OptionMatch::Partial(opts) => format!("only partially matches {}", opts.join(", ")),
+    };
+    ft.warnings.push(format!("{}.{} {}", fasm_name, feature, warning));
+    None
+}
+
 // Actual instance of a tile
 #[derive(Clone)]
 pub struct Tile {
@@ -577,6 +1105,11 @@ pub struct Tile {
     pub start_bit: usize,
     pub start_frame: usize,
     pub cram: BitMatrix,
+    // FASM features applied to the tile, the feature (by index) and value that last
+    // set each bit, and any conflicts between features
+    pub features: Vec<BitOwner>,
+    pub owners: BTreeMap<(usize, usize), (usize, bool)>,
+    pub conflicts: Vec<BitConflict>,
 }
 
 impl Tile {
@@ -590,16 +1123,46 @@ impl Tile {
             start_bit: data.start_bit,
             start_frame: data.start_frame,
             cram: BitMatrix::new(data.frames, data.bits),
+            features: Vec::new(),
+            owners: BTreeMap::new(),
+            conflicts: Vec::new(),
         }
     }
+    // Add a FASM feature that sets bits in the tile, returning its index for set_feature_bit
+    pub fn add_feature(&mut self, owner: BitOwner) -> usize {
+        self.features.push(owner);
+        self.features.len() - 1
+    }
+    // Set a bit on behalf of a FASM feature, recording a conflict if another
+    // feature already set it to the opposite value
+    pub fn set_feature_bit(&mut self, frame: usize, bit: usize, value: bool, feature: usize) {
+        if let Some((prev, prev_value)) = self.owners.insert((frame, bit), (feature, value)) {
+            // BASE_ enums set defaults that later features are expected to override
+            if prev_value != value && !self.features[prev].base {
+                self.conflicts.push(BitConflict {
+                    tile: self.name.to_string(),
+                    frame,
+                    bit,
+                    chip_frame: self.start_frame + frame,
+                    chip_bit: self.start_bit + bit,
+                    first: self.features[prev].clone(),
+                    second: self.features[feature].clone(),
+                    value,
+                });
+            }
+        }
+        self.cram.set(frame, bit, value);
+    }
     pub fn print(&self, mut out: &mut dyn Write) {
         if self.cram.any() {
             writeln!(&mut out, ".tile {}:{}", self.name, self.tiletype).unwrap();
             self.cram.print(&mut out);
         }
     }
-    pub fn from_fasm(&mut self, db: &mut Database, ft: &FasmTile) {
+    pub fn from_fasm(&mut self, db: &mut Database, ft: &FasmTile) -> Result<(), Vec<FasmError>> {
         let tdb = db.tile_bitdb(&self.family, &self.tiletype);
+        let loc = format!("tile {}", self.name);
+        let mut errors = Vec::new();
         for i in 0..2 {
             // Process "BASE_" enums first
             for (k, v) in ft
@@ -607,35 +1170,44 @@ impl Tile {
                 .iter()
                 .filter(|(k, _)| k.starts_with("BASE_") == (i == 0) && !k.starts_with("UNKNOWN."))
             {
-                let en = tdb.db.enums.get(k).unwrap_or_else(|| panic!("No enum named {} in tile {}.\n\
-Please make sure Oxide and nextpnr are up to date. If they are, consider reporting this as an issue.", k, self.name));
-                let opt = en.options.get(v).unwrap_or_else(|| panic!("No option named {} for enum {} in tile {}.\n\
-Valid options are: {}\n\
-Please make sure Oxide and nextpnr are up to date and input source code is meaningful. If they are, consider reporting this as an issue.",
-                            v, k, self.name,
-                            en.options.keys().cloned().collect::<Vec<String>>().join(", ")));
+                let en = match tdb.db.enums.get(k) {
+                    Some(en) => en,
+                    None => {
+                        errors.push(FasmError::new(ft.line_of(k), format!("no enum named {} in {}", k, loc)));
+                        continue;
+                    }
+                };
+                let opt = match en.options.get(v) {
+                    Some(opt) => opt,
+                    None => {
+                        errors.push(unknown_option_error(ft, k, v, &loc, en));
+                        continue;
+                    }
+                };
+                let owner = self.add_feature(BitOwner::new(format!("{}.{}.{}", self.name, k, v), ft.line_of(k), k));
                 for bit in opt.iter() {
-                    self.cram.set(bit.frame, bit.bit, !bit.invert);
+                    self.set_feature_bit(bit.frame, bit.bit, !bit.invert, owner);
                 }
             }
         }
         // Process words
         for (k, v) in ft.words.iter() {
-            let w = tdb.db.words.get(k).unwrap_or_else(|| panic!("No word named {} in tile {}.\n\
-Please make sure Oxide and nextpnr are up to date. If they are, consider reporting this as an issue.", k, self.name));
+            let w = match tdb.db.words.get(k) {
+                Some(w) => w,
+                None => {
+                    errors.push(FasmError::new(ft.line_of(k), format!("no word named {} in {}", k, loc)));
+                    continue;
+                }
+            };
             if (v.bits() as usize) > w.bits.len() {
-                panic!(
-                    "Word {} in tile {} has value width {} exceeding database width of {}",
-                    k,
-                    self.name,
-                    v.bits(),
-                    w.bits.len()
-                );
+                errors.push(word_width_error(ft, k, &loc, v.bits(), w));
+                continue;
             }
+            let owner = self.add_feature(BitOwner::new(format!("{}.{}", self.name, k), ft.line_of(k), k));
             for (i, wb) in w.bits.iter().enumerate() {
                 let bit_val = v.bit(i as u64);
                 for bit in wb {
-                    self.cram.set(bit.frame, bit.bit, bit.invert != bit_val);
+                    self.set_feature_bit(bit.frame, bit.bit, bit.invert != bit_val, owner);
                 }
             }
         }
@@ -648,73 +1220,61 @@ Please make sure Oxide and nextpnr are up to date. If they are, consider reporti
                 .map_or(None, |pips| pips.iter().find(|p| &p.from_wire == fw));
             match found_pip {
                 Some(p) => {
+                    let pip_key = format!("PIP.{}", tw);
+                    let owner = self.add_feature(BitOwner::new(format!("{}.{}.{}", self.name, pip_key, fw), ft.line_of(&pip_key), ""));
                     for bit in p.bits.iter() {
-                        self.cram.set(bit.frame, bit.bit, !bit.invert);
+                        self.set_feature_bit(bit.frame, bit.bit, !bit.invert, owner);
                     }
                 }
                 None => {
-                    // Panic iff fixed connection doesn't exist
+                    // Error iff fixed connection doesn't exist
                     let found_fc = tdb
                         .db
                         .conns
                         .get(tw)
                         .map_or(None, |conns| conns.iter().find(|c| &c.from_wire == fw));
                     if found_fc.is_none() {
-                        panic!("No pip {}.{} in tile {}.\n\
-Please make sure Oxide and nextpnr are up to date. If they are, consider reporting this as an issue.", fw, tw, self.name);
+                        errors.push(FasmError::new(
+                            ft.line_of(&format!("PIP.{}", tw)),
+                            format!("no pip {}.{} in {}", fw, tw, loc),
+                        ));
                     }
                 }
             }
         }
         // Process unknowns
         for (f, b) in ft.unknowns.iter() {
-            self.cram.set(*f, *b, true);
+            let unknown_key = format!("UNKNOWN.{}.{}", f, b);
+            let owner = self.add_feature(BitOwner::new(format!("{}.{}", self.name, unknown_key), ft.line_of(&unknown_key), ""));
+            self.set_feature_bit(*f, *b, true, owner);
         }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
     }
-    pub fn write_fasm(&self, db: &mut Database, mut out: &mut dyn Write) {
+    // Decode the tile CRAM into FASM features. Each enum and pip mux is decoded as an
+    // exact cover of its bits, and set bits that can't be explained are listed as unknowns
+    pub fn decode_fasm(&self, db: &mut Database) -> FasmTile {
         let tdb = db.tile_bitdb(&self.family, &self.tiletype);
+        let mut ft = FasmTile::new();
         let fasm_name = self.name.replace(':', "__");
+        let get_bit = |cb: &ConfigBit| self.cram.get(cb.frame, cb.bit);
         let mut known_bits = BTreeSet::<(usize, usize)>::new();
-        let mut total_matches = 0;
+        let shared = tdb.shared_bits();
         for (to_wire, pips) in tdb.db.pips.iter() {
-            let best_match = pips
-                .iter()
-                .filter(|p| {
-                    p.bits.iter().any(|cb| !cb.invert)
-                        && p.bits
-                            .iter()
-                            .all(|cb| self.cram.get(cb.frame, cb.bit) == !cb.invert)
-                })
-                .max_by_key(|p| p.bits.len());
-            if let Some(m) = best_match {
-                writeln!(
-                    &mut out,
-                    "{}.PIP.{}.{}",
-                    fasm_name,
-                    to_wire.replace(':', "__"),
-                    m.from_wire.replace(':', "__")
-                )
-                .unwrap();
-                let mut matched_bits = m.bits.iter().map(|cb| (cb.frame, cb.bit)).collect();
-                known_bits.append(&mut matched_bits);
-                total_matches += 1;
+            let options: Vec<(&str, &BTreeSet<ConfigBit>)> =
+                pips.iter().map(|p| (p.from_wire.as_str(), &p.bits)).collect();
+            let feature = format!("PIP.{}", to_wire.replace(':', "__"));
+            let m = match_options(&options, None, get_bit, shared);
+            if let Some(from_wire) = decode_match(&mut ft, &fasm_name, &feature, m) {
+                ft.pips.insert(to_wire.to_string(), from_wire.to_string());
+                let bits = options.iter().find(|(k, _)| *k == from_wire).unwrap().1;
+                known_bits.extend(bits.iter().map(|cb| (cb.frame, cb.bit)));
             }
         }
         for (name, edata) in tdb.db.enums.iter() {
-            let best_match = edata
-                .options
-                .iter()
-                .filter(|(_k, v)| {
-                    v.iter().any(|cb| !cb.invert)
-                        && v.iter()
-                            .all(|cb| self.cram.get(cb.frame, cb.bit) == !cb.invert)
-                })
-                .max_by_key(|(_k, v)| v.len());
-            if let Some((opt, bits)) = best_match {
-                writeln!(&mut out, "{}.{}.{}", fasm_name, name, opt).unwrap();
-                let mut matched_bits = bits.iter().map(|cb| (cb.frame, cb.bit)).collect();
-                known_bits.append(&mut matched_bits);
-                total_matches += 1;
+            let m = decode_enum(edata, get_bit, shared);
+            if let Some(opt) = decode_match(&mut ft, &fasm_name, name, m) {
+                ft.enums.insert(name.to_string(), opt.to_string());
+                known_bits.extend(edata.options[opt].iter().map(|cb| (cb.frame, cb.bit)));
             }
         }
         for (name, wdata) in tdb.db.words.iter() {
@@ -727,30 +1287,14 @@ Please make sure Oxide and nextpnr are up to date. If they are, consider reporti
             {
                 continue;
             }
-            let bitstr: String = wdata
-                .bits
-                .iter()
-                .rev()
-                .map(|b| {
-                    match b
-                        .iter()
-                        .all(|cb| self.cram.get(cb.frame, cb.bit) == !cb.invert)
-                    {
-                        true => '1',
-                        false => '0',
-                    }
-                })
-                .collect();
-            writeln!(
-                &mut out,
-                "{}.{}[{}:0] = {}'b{}",
-                fasm_name,
-                name,
-                wdata.bits.len() - 1,
-                wdata.bits.len(),
-                bitstr
-            )
-            .unwrap();
+            let mut value = Integer::from(0);
+            for (i, b) in wdata.bits.iter().enumerate() {
+                value.set_bit(
+                    i as u64,
+                    b.iter().all(|cb| self.cram.get(cb.frame, cb.bit) != cb.invert),
+                );
+            }
+            ft.words.insert(name.to_string(), value);
             let mut matched_bits = wdata
                 .bits
                 .iter()
@@ -758,25 +1302,306 @@ Please make sure Oxide and nextpnr are up to date. If they are, consider reporti
                 .map(|cb| (cb.frame, cb.bit))
                 .collect();
             known_bits.append(&mut matched_bits);
-            total_matches += 1;
         }
         for aon in tdb.db.always_on.iter() {
             if self.cram.get(aon.frame, aon.bit) {
                 known_bits.insert((aon.frame, aon.bit));
             } else {
-                warn!("Supposedly always on bit F{}B{} in {} found to be cleared!\n", aon.frame, aon.bit, fasm_name);
+                ft.warnings.push(format!(
+                    "supposedly always on bit F{}B{} in {} found to be cleared",
+                    aon.frame, aon.bit, fasm_name
+                ));
             }
         }
         for f in 0..self.cram.frames {
             for b in 0..self.cram.bits {
                 if self.cram.get(f, b) && !known_bits.contains(&(f, b)) {
-                    writeln!(&mut out, "{}.UNKNOWN.{}.{}", fasm_name, f, b).unwrap();
-                    total_matches += 1;
+                    ft.unknowns.push((f, b));
                 }
             }
         }
-        if total_matches > 0 {
+        ft
+    }
+    // Write the tile as FASM, returning any warnings from decoding
+    pub fn write_fasm(&self, db: &mut Database, mut out: &mut dyn Write) -> Vec<String> {
+        let ft = self.decode_fasm(db);
+        let tdb = db.tile_bitdb(&self.family, &self.tiletype);
+        let fasm_name = self.name.replace(':', "__");
+        for (to_wire, from_wire) in ft.pips.iter() {
+            writeln!(
+                &mut out,
+                "{}.PIP.{}.{}",
+                fasm_name,
+                to_wire.replace(':', "__"),
+                from_wire.replace(':', "__")
+            )
+            .unwrap();
+        }
+        for (name, opt) in ft.enums.iter() {
+            writeln!(&mut out, "{}.{}.{}", fasm_name, name, opt).unwrap();
+        }
+        for (name, value) in ft.words.iter() {
+            let width = tdb.db.words[name].bits.len();
+            writeln!(
+                &mut out,
+                "{}.{}[{}:0] = {}'b{}",
+                fasm_name,
+                name,
+                width - 1,
+                width,
+                word_bitstr(value, width)
+            )
+            .unwrap();
+        }
+        for (f, b) in ft.unknowns.iter() {
+            writeln!(&mut out, "{}.UNKNOWN.{}.{}", fasm_name, f, b).unwrap();
+        }
+        if !ft.pips.is_empty() || !ft.enums.is_empty() || !ft.words.is_empty() || !ft.unknowns.is_empty() {
             writeln!(&mut out, "").unwrap();
         }
+        ft.warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Simple reference pattern, with a mix of set and clear bits
+    fn pattern(frame: usize, bit: usize) -> bool {
+        (frame * 7 + bit * 3) % 5 < 2
+    }
+
+    fn pattern_matrix(frames: usize, bits: usize) -> BitMatrix {
+        let mut m = BitMatrix::new(frames, bits);
+        for f in 0..frames {
+            for b in 0..bits {
+                m.set(f, b, pattern(f, b));
+            }
+        }
+        m
+    }
+
+    fn config_bits(bits: &[(usize, usize, bool)]) -> BTreeSet<ConfigBit> {
+        bits.iter()
+            .map(|&(frame, bit, invert)| ConfigBit { frame, bit, invert })
+            .collect()
+    }
+
+    // Match options against a CRAM with the given bits set
+    fn match_set<'a>(
+        options: &[(&'a str, &'a BTreeSet<ConfigBit>)],
+        set: &[(usize, usize)],
+        shared: &[(usize, usize)],
+    ) -> OptionMatch<'a> {
+        let shared: HashSet<(usize, usize)> = shared.iter().copied().collect();
+        match_options(options, None, |cb| set.contains(&(cb.frame, cb.bit)), &shared)
+    }
+
+    #[test]
+    fn match_options_exact() {
+        let (a, b) = (config_bits(&[(0, 0, false)]), config_bits(&[(0, 1, false), (1, 1, false)]));
+        let options = [("A", &a), ("B", &b)];
+        assert_eq!(match_set(&options, &[], &[]), OptionMatch::Default);
+        assert_eq!(match_set(&options, &[(0, 0)], &[]), OptionMatch::Exact("A"));
+        assert_eq!(match_set(&options, &[(0, 1), (1, 1)], &[]), OptionMatch::Exact("B"));
+    }
+
+    #[test]
+    fn match_options_ambiguous() {
+        // Both options only differ in bits shared with other features
+        let (a, b) = (
+            config_bits(&[(0, 0, false), (0, 2, false)]),
+            config_bits(&[(0, 1, false), (0, 2, false)]),
+        );
+        let options = [("A", &a), ("B", &b)];
+        assert_eq!(
+            match_set(&options, &[(0, 0), (0, 1), (0, 2)], &[(0, 0), (0, 1)]),
+            OptionMatch::Ambiguous(vec!["A", "B"])
+        );
+        // Without shared bits, neither option explains the other's bits
+        assert_eq!(
+            match_set(&options, &[(0, 0), (0, 1), (0, 2)], &[]),
+            OptionMatch::Partial(vec!["A", "B"])
+        );
+    }
+
+    #[test]
+    fn match_options_partial() {
+        let (a, b) = (config_bits(&[(0, 0, false), (0, 1, false)]), config_bits(&[(0, 2, false)]));
+        let options = [("A", &a), ("B", &b)];
+        assert_eq!(match_set(&options, &[(0, 0), (0, 2)], &[]), OptionMatch::Partial(vec!["B"]));
+        assert_eq!(match_set(&options, &[(0, 0)], &[]), OptionMatch::Partial(vec![]));
+    }
+
+    #[test]
+    fn match_options_subset_pips() {
+        // One pip's bits are a subset of another's
+        let (a, b) = (config_bits(&[(0, 0, false)]), config_bits(&[(0, 0, false), (0, 1, false)]));
+        let options = [("A", &a), ("B", &b)];
+        assert_eq!(match_set(&options, &[(0, 0)], &[]), OptionMatch::Exact("A"));
+        assert_eq!(match_set(&options, &[(0, 0), (0, 1)], &[]), OptionMatch::Exact("B"));
+        assert_eq!(match_set(&options, &[(0, 1)], &[]), OptionMatch::Partial(vec![]));
+    }
+
+    #[test]
+    fn match_options_inverted() {
+        // Options that only clear bits look the same as writing nothing
+        let (a, b) = (config_bits(&[(0, 0, true)]), config_bits(&[(0, 1, true)]));
+        let options = [("A", &a), ("B", &b)];
+        assert_eq!(match_set(&options, &[], &[]), OptionMatch::Default);
+        // No option leaves a bit set
+        assert_eq!(match_set(&options, &[(0, 1)], &[]), OptionMatch::Partial(vec!["A"]));
+        assert_eq!(match_set(&options, &[(0, 0), (0, 1)], &[]), OptionMatch::Partial(vec![]));
+        // Even a single option that clears bits is not chosen over the default
+        let c = config_bits(&[(0, 2, false)]);
+        let options = [("A", &a), ("C", &c)];
+        assert_eq!(match_set(&options, &[], &[]), OptionMatch::Default);
+        assert_eq!(match_set(&options, &[(0, 2)], &[]), OptionMatch::Exact("C"));
+    }
+
+    #[test]
+    fn match_options_declared_default() {
+        // The default sets a bit that the other option clears
+        let (d, o, z) = (config_bits(&[(0, 0, false)]), config_bits(&[(0, 0, true)]), config_bits(&[]));
+        let options = [("D", &d), ("O", &o)];
+        let match_default = |default, set: &[(usize, usize)]| {
+            match_options(&options, default, |cb| set.contains(&(cb.frame, cb.bit)), &HashSet::new())
+        };
+        assert_eq!(match_default(Some("D"), &[]), OptionMatch::Exact("O"));
+        assert_eq!(match_default(Some("D"), &[(0, 0)]), OptionMatch::Exact("D"));
+        // Writing nothing gives the default if it only clears bits, or isn't known
+        assert_eq!(match_default(Some("O"), &[]), OptionMatch::Default);
+        assert_eq!(match_default(None, &[]), OptionMatch::Default);
+        // An option with no bits is chosen the same way
+        let options = [("D", &d), ("Z", &z)];
+        assert_eq!(match_options(&options, Some("D"), |_| false, &HashSet::new()), OptionMatch::Exact("Z"));
+        let options = [("D", &d), ("O", &o), ("Z", &z)];
+        assert_eq!(
+            match_options(&options, Some("D"), |_| false, &HashSet::new()),
+            OptionMatch::Ambiguous(vec!["O", "Z"])
+        );
+    }
+
+    #[test]
+    fn read_row_bits_across_words() {
+        let row = [0x8000_0000_0000_0001, 0x0000_0000_0000_0005];
+        assert_eq!(read_row_bits(&row, 0, 1), 0x1);
+        assert_eq!(read_row_bits(&row, 63, 2), 0x3);
+        assert_eq!(read_row_bits(&row, 63, 4), 0xB);
+        assert_eq!(read_row_bits(&row, 0, 64), 0x8000_0000_0000_0001);
+        assert_eq!(read_row_bits(&row, 1, 64), 0xC000_0000_0000_0000);
+        assert_eq!(read_row_bits(&row, 64, 3), 0x5);
+    }
+
+    #[test]
+    fn write_row_bits_across_words() {
+        let mut row = [!0u64, !0u64];
+        write_row_bits(&mut row, 62, 4, 0x5);
+        assert_eq!(row, [0x7FFF_FFFF_FFFF_FFFF, 0xFFFF_FFFF_FFFF_FFFD]);
+        // Bits above len are ignored
+        write_row_bits(&mut row, 60, 2, 0xFF);
+        assert_eq!(row[0], 0x7FFF_FFFF_FFFF_FFFF);
+        let mut row = [0u64, 0u64];
+        write_row_bits(&mut row, 32, 64, 0x1234_5678_9ABC_DEF0);
+        assert_eq!(row, [0x9ABC_DEF0_0000_0000, 0x0000_0000_1234_5678]);
+        assert_eq!(read_row_bits(&row, 32, 64), 0x1234_5678_9ABC_DEF0);
+    }
+
+    #[test]
+    fn copy_row_bits_unaligned() {
+        let from: Vec<u64> = (0..4).map(|i| 0x0123_4567_89AB_CDEF_u64.rotate_left(i * 13)).collect();
+        for &(to_start, from_start, len) in &[(0, 0, 256), (3, 61, 130), (64, 1, 63), (127, 64, 65), (5, 200, 56)] {
+            let mut to = vec![0xAAAA_AAAA_AAAA_AAAA_u64; 4];
+            let before = to.clone();
+            copy_row_bits(&mut to, to_start, &from, from_start, len);
+            for i in 0..256 {
+                let get = |row: &[u64], b: usize| (row[b / 64] >> (b % 64)) & 0x1;
+                let expected = if i >= to_start && i < to_start + len {
+                    get(&from, from_start + i - to_start)
+                } else {
+                    get(&before, i)
+                };
+                assert_eq!(get(&to, i), expected, "bit {} of copy {:?}", i, (to_start, from_start, len));
+            }
+        }
+    }
+
+    #[test]
+    fn count_set_bits() {
+        let mut m = BitMatrix::new(3, 130);
+        assert_eq!(m.count(), 0);
+        assert!(!m.any());
+        m.set(0, 0, true);
+        m.set(1, 63, true);
+        m.set(1, 64, true);
+        m.set(2, 129, true);
+        assert_eq!(m.count(), 4);
+        m.set(1, 63, false);
+        assert_eq!(m.count(), 3);
+        let m = pattern_matrix(10, 130);
+        let expected = (0..10).flat_map(|f| (0..130).map(move |b| (f, b))).filter(|&(f, b)| pattern(f, b)).count();
+        assert_eq!(m.count(), expected);
+    }
+
+    #[test]
+    fn copy_window_unaligned() {
+        let tile = pattern_matrix(4, 70);
+        let mut cram = BitMatrix::new(10, 200);
+        cram.set(2, 59, true);
+        cram.set(2, 130, true);
+        cram.copy_window(&tile, 2, 60);
+        for f in 0..10 {
+            for b in 0..200 {
+                let expected = if (2..6).contains(&f) && (60..130).contains(&b) {
+                    pattern(f - 2, b - 60)
+                } else {
+                    (f, b) == (2, 59) || (f, b) == (2, 130)
+                };
+                assert_eq!(cram.get(f, b), expected, "F{}B{}", f, b);
+            }
+        }
+        let mut back = BitMatrix::new(4, 70);
+        back.copy_from_window(&cram, 2, 60);
+        assert!(back.delta(&tile).is_empty());
+    }
+
+    #[test]
+    fn copy_from_window_unaligned() {
+        let cram = pattern_matrix(8, 300);
+        for &start_bit in &[0, 1, 63, 64, 65, 127, 200] {
+            let mut tile = BitMatrix::new(3, 79);
+            tile.copy_from_window(&cram, 5, start_bit);
+            for f in 0..3 {
+                for b in 0..79 {
+                    assert_eq!(tile.get(f, b), pattern(f + 5, b + start_bit), "F{}B{} at {}", f, b, start_bit);
+                }
+            }
+            // Unused bits at the end of each row must stay clear
+            assert_eq!(tile.count(), tile.set_bits().len());
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn copy_window_out_of_range() {
+        let mut cram = BitMatrix::new(10, 100);
+        cram.copy_window(&BitMatrix::new(2, 40), 0, 61);
+    }
+
+    #[test]
+    #[should_panic]
+    fn copy_from_window_out_of_range() {
+        let cram = BitMatrix::new(10, 100);
+        BitMatrix::new(2, 40).copy_from_window(&cram, 0, 61);
+    }
+
+    #[test]
+    fn delta_lists_changed_bits() {
+        let base = pattern_matrix(4, 130);
+        let mut m = base.clone();
+        m.set(0, 63, !m.get(0, 63));
+        m.set(3, 129, !m.get(3, 129));
+        assert_eq!(m.delta(&base), vec![(0, 63, !pattern(0, 63)), (3, 129, !pattern(3, 129))]);
     }
 }
@@ -0,0 +1,284 @@
+use crate::chip::*;
+use crate::database::*;
+use crate::routing::*;
+use crate::wires::*;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Write;
+
+/*
+Global clock network usage analysis
+
+Nets are recovered as for trace_nets, and any net routed through the global clock
+network (MIDMUX, CMUX, HROW, spine and branch wires, or edge clocks) is reported as a
+clock. DCCs and DCSs are bels rather than routing, so a clock through them is split into
+a net feeding the bel input and a net from its output. These are joined back together,
+so the source of a clock is the PLL, IO or fabric output before any DCC or DCS.
+
+The problems reported are:
+ - double_driven: a clock network wire driven by more than one enabled pip
+ - no_source: a clock path starting partway through the network, or a DCC or DCS with
+   its output used but no input driven
+ - dead_end: a MIDMUX, CMUX, HROW or spine wire that is driven but drives nothing, or a
+   DCC or DCS whose input is driven but whose output is unused
+*/
+
+lazy_static! {
+    // DCC and DCS outputs, with the location prefix and bel
+    static ref DCC_OUT_RE: Regex = Regex::new(r"^(R\d+C\d+_)JCLKO_DCC_DCC(\d+)$").unwrap();
+    static ref DCS_OUT_RE: Regex = Regex::new(r"^(R\d+C\d+_)JDCSOUT_DCS_DCSIP$").unwrap();
+    // DCC and DCS clock inputs
+    static ref CLOCK_BEL_IN_RE: Regex = Regex::new(r"^(R\d+C\d+_)J(?:CLKI_DCC_DCC(\d+)|CLK[01]_DCS_DCSIP)$").unwrap();
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LevelUse {
+    pub level: &'static str,
+    pub wires: Vec<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ClockNet {
+    // Drivers of the clock, before any DCC or DCS (more than one for a DCS)
+    pub sources: Vec<String>,
+    // DCCs and DCSs the clock passes through
+    pub via: Vec<String>,
+    // Clock network wires used, from the MIDMUX and CMUX down to the branches
+    pub resources: Vec<LevelUse>,
+    // Clock regions reached by branches of the clock
+    pub regions: Vec<String>,
+    pub sinks: usize,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ClockIssue {
+    pub kind: &'static str,
+    pub wire: String,
+    pub detail: String,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ClockReport {
+    pub clocks: Vec<ClockNet>,
+    pub issues: Vec<ClockIssue>,
+}
+
+// The DCC or DCS driving a wire, and its clock inputs
+fn clock_bel_inputs(wire: &str) -> Option<(String, Vec<String>)> {
+    if let Some(c) = DCC_OUT_RE.captures(wire) {
+        Some((format!("{}DCC{}", &c[1], &c[2]), vec![format!("{}JCLKI_DCC_DCC{}", &c[1], &c[2])]))
+    } else {
+        DCS_OUT_RE.captures(wire).map(|c| {
+            let inputs = (0..2).map(|i| format!("{}JCLK{}_DCS_DCSIP", &c[1], i)).collect();
+            (format!("{}DCS0", &c[1]), inputs)
+        })
+    }
+}
+
+// The output of the DCC or DCS that a wire is a clock input of
+fn clock_bel_output(wire: &str) -> Option<String> {
+    CLOCK_BEL_IN_RE.captures(wire).map(|c| match c.get(2) {
+        Some(dcc) => format!("{}JCLKO_DCC_DCC{}", &c[1], dcc.as_str()),
+        None => format!("{}JDCSOUT_DCS_DCSIP", &c[1]),
+    })
+}
+
+fn issue(kind: &'static str, wire: &str, detail: String) -> ClockIssue {
+    ClockIssue {
+        kind,
+        wire: wire.to_string(),
+        detail,
+    }
+}
+
+// Clock network pip muxes with more than one input enabled. These do not decode to
+// pips, so are found by matching the pips of each mux against the bits of the tile.
+fn multiple_inputs(chip: &Chip, db: &mut Database, glb: &DeviceGlobalsData) -> Vec<ClockIssue> {
+    let mut issues = Vec::new();
+    for tile in chip.tiles.iter().filter(|t| t.cram.any()) {
+        let tdb = db.tile_bitdb(&chip.family, &tile.tiletype);
+        let get_bit = |cb: &ConfigBit| tile.cram.get(cb.frame, cb.bit);
+        for (to_wire, pips) in tdb.db.pips.iter().filter(|(w, _)| clock_level(w).is_some()) {
+            let options: Vec<(&str, &BTreeSet<ConfigBit>)> =
+                pips.iter().map(|p| (p.from_wire.as_str(), &p.bits)).collect();
+            let inputs = match match_options(&options, None, get_bit, tdb.shared_bits()) {
+                OptionMatch::Ambiguous(inputs) => inputs,
+                OptionMatch::Partial(inputs) if inputs.len() > 1 => inputs,
+                _ => continue,
+            };
+            let inputs: Vec<String> = inputs.iter().map(|w| denormalize_wire(chip, glb, tile, w)).collect();
+            let wire = denormalize_wire(chip, glb, tile, to_wire);
+            issues.push(issue("double_driven", &wire, format!("driven by {} in {}", inputs.join(", "), tile.name)));
+        }
+    }
+    issues
+}
+
+struct ClockTracer<'a> {
+    nets: &'a [RoutedNet],
+    // Net with each wire as a sink
+    by_sink: HashMap<&'a str, usize>,
+    issues: Vec<ClockIssue>,
+}
+
+impl<'a> ClockTracer<'a> {
+    // Walk back from a net through DCCs and DCSs to the clock sources, collecting the
+    // nets along the way
+    fn trace_back(&mut self, idx: usize, clock: &mut ClockNet, nets: &mut Vec<usize>) {
+        if nets.contains(&idx) {
+            return;
+        }
+        nets.push(idx);
+        let driver = &self.nets[idx].driver;
+        let (bel, inputs) = match clock_bel_inputs(driver) {
+            Some(bel) => bel,
+            None => {
+                clock.sources.push(driver.to_string());
+                return;
+            }
+        };
+        clock.via.push(bel.to_string());
+        let feeders: Vec<usize> = inputs.iter().filter_map(|i| self.by_sink.get(i.as_str()).copied()).collect();
+        if feeders.is_empty() {
+            self.issues.push(issue("no_source", driver, format!("output of {} is used but no input is driven", bel)));
+        }
+        for f in feeders {
+            self.trace_back(f, clock, nets);
+        }
+    }
+}
+
+pub fn analyse_clocks(chip: &Chip, db: &mut Database) -> ClockReport {
+    let graph = RoutingGraph::new(chip, db);
+    analyse_clocks_graph(chip, db, &graph)
+}
+
+// Analyse the clocks of a chip, given its routing graph
+pub fn analyse_clocks_graph(chip: &Chip, db: &mut Database, graph: &RoutingGraph) -> ClockReport {
+    let glb = db.device_globals(&chip.family, &chip.device).clone();
+    let nets = graph.nets();
+    let mut tracer = ClockTracer {
+        nets: &nets,
+        by_sink: nets
+            .iter()
+            .enumerate()
+            .flat_map(|(i, n)| n.sinks.iter().map(move |s| (s.as_str(), i)))
+            .collect(),
+        issues: multiple_inputs(chip, db, &glb),
+    };
+    let drivers: BTreeSet<&str> = nets.iter().map(|n| n.driver.as_str()).collect();
+    let uses_network = |n: &RoutedNet| n.pips.iter().any(|p| clock_level(&p.to_wire).is_some());
+    let only_feeds_bels = |n: &RoutedNet| !n.sinks.is_empty() && n.sinks.iter().all(|s| CLOCK_BEL_IN_RE.is_match(s));
+
+    let mut report = ClockReport::default();
+    for (idx, net) in nets.iter().enumerate() {
+        if only_feeds_bels(net) {
+            // Reported as part of the clock from the bel output, if there is one
+            for s in net.sinks.iter() {
+                if !clock_bel_output(s).is_some_and(|out| drivers.contains(out.as_str())) {
+                    tracer.issues.push(issue("dead_end", s, format!("driven by {} but the output is unused", net.driver)));
+                }
+            }
+            continue;
+        }
+        if !uses_network(net) && clock_bel_inputs(&net.driver).is_none() {
+            continue;
+        }
+        let mut clock = ClockNet {
+            sources: Vec::new(),
+            via: Vec::new(),
+            resources: Vec::new(),
+            regions: Vec::new(),
+            sinks: net.sinks.len(),
+        };
+        let mut clock_nets = Vec::new();
+        tracer.trace_back(idx, &mut clock, &mut clock_nets);
+        let mut levels: BTreeMap<ClockLevel, BTreeSet<&str>> = BTreeMap::new();
+        for n in clock_nets.iter().map(|&i| &nets[i]) {
+            let wires = std::iter::once(&n.driver).chain(n.pips.iter().map(|p| &p.to_wire));
+            for w in wires {
+                if let Some(level) = clock_level(w) {
+                    levels.entry(level).or_default().insert(w);
+                }
+            }
+        }
+        let regions: BTreeSet<String> = levels
+            .get(&ClockLevel::Branch)
+            .into_iter()
+            .flatten()
+            .filter_map(|w| {
+                let (loc, _) = w.split_once('_')?;
+                let (y, x) = loc.strip_prefix('R')?.split_once('C')?;
+                Some(glb.clock_region(x.parse().ok()?, y.parse().ok()?))
+            })
+            .collect();
+        clock.regions = regions.into_iter().collect();
+        clock.resources = levels
+            .iter()
+            .map(|(level, wires)| LevelUse {
+                level: level.name(),
+                wires: wires.iter().map(|w| w.to_string()).collect(),
+            })
+            .collect();
+        if clock_level(&net.driver).is_some() {
+            tracer.issues.push(issue("no_source", &net.driver, "clock path starts inside the clock network".to_string()));
+        }
+        report.clocks.push(clock);
+    }
+
+    // Check every enabled pip into the clock network, not only those of reported clocks,
+    // for wires driven from more than one tile
+    let mut drivers_of: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for p in nets.iter().flat_map(|n| n.pips.iter()) {
+        if let Some(level) = clock_level(&p.to_wire) {
+            drivers_of.entry(&p.to_wire).or_default().insert(&p.from_wire);
+            if level > ClockLevel::Eclk && level < ClockLevel::Branch && !graph.drives_anything(&p.to_wire) {
+                tracer.issues.push(issue("dead_end", &p.to_wire, format!("driven by {} but drives nothing", p.from_wire)));
+            }
+        }
+    }
+    for (wire, from) in drivers_of.iter().filter(|(_, from)| from.len() > 1) {
+        let from: Vec<&str> = from.iter().copied().collect();
+        tracer.issues.push(issue("double_driven", wire, format!("driven by {}", from.join(", "))));
+    }
+    report.issues = tracer.issues;
+    report.issues.sort_by(|a, b| (a.kind, &a.wire).cmp(&(b.kind, &b.wire)));
+    report.issues.dedup();
+    report.clocks.sort_by(|a, b| a.sources.cmp(&b.sources));
+    report
+}
+
+// Print each clock with the network resources it uses, then any problems found. Spine
+// and branch wires are summarised by count.
+pub fn write_clock_report(out: &mut dyn Write, report: &ClockReport) -> std::io::Result<()> {
+    for c in report.clocks.iter() {
+        if c.sources.is_empty() {
+            write!(out, "clock (no source)")?;
+        } else {
+            write!(out, "clock {}", c.sources.join(", "))?;
+        }
+        if !c.via.is_empty() {
+            write!(out, " via {}", c.via.join(", "))?;
+        }
+        writeln!(out)?;
+        for l in c.resources.iter() {
+            if l.level == "spine" || l.level == "branch" {
+                writeln!(out, "    {:<12} {} wires", l.level, l.wires.len())?;
+            } else {
+                writeln!(out, "    {:<12} {}", l.level, l.wires.join(" "))?;
+            }
+        }
+        if !c.regions.is_empty() {
+            writeln!(out, "    {:<12} {}", "regions", c.regions.join(" "))?;
+        }
+        writeln!(out, "    {:<12} {}", "sinks", c.sinks)?;
+    }
+    if !report.issues.is_empty() {
+        writeln!(out, "issues:")?;
+        for i in report.issues.iter() {
+            writeln!(out, "    {:<14} {}: {}", i.kind, i.wire, i.detail)?;
+        }
+    }
+    Ok(())
+}
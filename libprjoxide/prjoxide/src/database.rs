@@ -1,6 +1,7 @@
 use ron::ser::PrettyConfig;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::cell::OnceCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
@@ -132,6 +133,26 @@ impl DeviceGlobalsData {
                 .map(|s| (*spine_col, s.spine_row)),
         }
     }
+    // Clock region of a location, named by the spine row and HROW covering it
+    pub fn clock_region(&self, x: i32, y: i32) -> String {
+        let spine = self
+            .spines
+            .iter()
+            .find(|s| y >= s.from_row as i32 && y <= s.to_row as i32);
+        // Each HROW drives a set of spine columns, so take the one with the nearest column
+        let hrow = self.hrows.iter().min_by_key(|h| {
+            h.spine_cols
+                .iter()
+                .chain(std::iter::once(&h.hrow_col))
+                .map(|&c| (c as i32 - x).abs())
+                .min()
+                .unwrap_or(i32::MAX)
+        });
+        match (spine, hrow) {
+            (Some(s), Some(h)) => format!("R{}C{}", s.spine_row, h.hrow_col),
+            _ => "other".to_string(),
+        }
+    }
     pub fn is_hrow_loc(&self, x: usize, y: usize) -> bool {
         self.hrows.iter().any(|h| h.hrow_col == x) && self.spines.iter().any(|s| s.spine_row == y)
     }
@@ -170,12 +191,32 @@ pub struct PadData {
     pub vref: i32,
 }
 
+impl PadData {
+    // Name of the pad as used in pinouts (e.g. PR3A), or its function for special IO
+    pub fn name(&self) -> String {
+        if self.side.is_empty() || self.pio < 0 {
+            self.func.first().cloned().unwrap_or_default()
+        } else {
+            format!("P{}{}{}", self.side, self.offset, (b'A' + self.pio as u8) as char)
+        }
+    }
+}
+
 #[derive(Deserialize, Clone)]
 pub struct DeviceIOData {
     pub packages: Vec<String>,
     pub pads: Vec<PadData>
 }
 
+impl DeviceIOData {
+    // Index of a package, by long (CABGA400) or short (BG400) name
+    pub fn package_index(&self, package: &str) -> Option<usize> {
+        self.packages
+            .iter()
+            .position(|p| p == package || crate::chip::Chip::get_package_short_name(p).is_ok_and(|s| s == package))
+    }
+}
+
 // Interconnect timing data
 #[derive(Deserialize, Clone)]
 pub struct PipClassDelay {
@@ -261,6 +302,22 @@ pub struct ConfigEnumData {
     #[serde(default)]
     #[serde(skip_serializing_if = "String::is_empty")]
     pub desc: String,
+    // Option of the base design used for fuzzing, if known
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct ConfigDynamicData {
+    // Only dynamic when this enum is set to one of the options below,
+    // or always if no enum is given
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub enum_name: String,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub options: BTreeSet<String>,
 }
 
 fn is_false(x: &bool) -> bool {
@@ -284,6 +341,11 @@ pub struct TileBitsDatabase {
     #[serde(default)]
     #[serde(skip_serializing_if = "BTreeSet::is_empty")]
     pub always_on: BTreeSet<ConfigBit>,
+    // Words whose bits can change at runtime (such as LUT RAM initialisation),
+    // which are masked out of the frame ECC
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub dynamic: BTreeMap<String, ConfigDynamicData>,
 }
 
 impl TileBitsDatabase {
@@ -308,12 +370,34 @@ impl TileBitsDatabase {
         }
         return sinks;
     }
+
+    // Bits used by more than one enum, pip mux or word
+    pub fn shared_bits(&self) -> HashSet<(usize, usize)> {
+        let mut seen = HashSet::new();
+        let mut shared = HashSet::new();
+        let features = self
+            .pips
+            .values()
+            .map(|pips| pips.iter().flat_map(|p| p.bits.iter()).collect::<BTreeSet<_>>())
+            .chain(self.enums.values().map(|e| e.options.values().flatten().collect()))
+            .chain(self.words.values().map(|w| w.bits.iter().flatten().collect()));
+        for bits in features {
+            for cb in bits.iter().map(|cb| (cb.frame, cb.bit)).collect::<BTreeSet<_>>() {
+                if !seen.insert(cb) {
+                    shared.insert(cb);
+                }
+            }
+        }
+        shared
+    }
 }
 
 pub struct TileBitsData {
     tiletype: String,
     pub db: TileBitsDatabase,
     dirty: bool,
+    // Cached shared bits, cleared when pips, words or enums are added
+    shared: OnceCell<HashSet<(usize, usize)>>,
 }
 
 impl TileBitsData {
@@ -322,8 +406,13 @@ impl TileBitsData {
             tiletype: tiletype.to_string(),
             db: db.clone(),
             dirty: false,
+            shared: OnceCell::new(),
         }
     }
+    // Bits used by more than one enum, pip mux or word
+    pub fn shared_bits(&self) -> &HashSet<(usize, usize)> {
+        self.shared.get_or_init(|| self.db.shared_bits())
+    }
     pub fn add_pip(&mut self, from: &str, to: &str, bits: BTreeSet<ConfigBit>) {
         if !self.db.pips.contains_key(to) {
             self.db.pips.insert(to.to_string(), Vec::new());
@@ -341,6 +430,7 @@ impl TileBitsData {
             }
         }
         self.dirty = true;
+        self.shared.take();
         ac.push(ConfigPipData {
             from_wire: from.to_string(),
             bits: bits.clone(),
@@ -348,6 +438,7 @@ impl TileBitsData {
     }
     pub fn add_word(&mut self, name: &str, desc: &str, bits: Vec<BTreeSet<ConfigBit>>) {
         self.dirty = true;
+        self.shared.take();
         match self.db.words.get_mut(name) {
             None => {
                 self.db.words.insert(
@@ -395,6 +486,7 @@ impl TileBitsData {
                 ConfigEnumData {
                     options: BTreeMap::new(),
                     desc: desc.to_string(),
+                    default: None,
                 },
             );
         }
@@ -415,6 +507,15 @@ impl TileBitsData {
             None => {
                 ec.options.insert(option.to_string(), bits);
                 self.dirty = true;
+                self.shared.take();
+            }
+        }
+    }
+    pub fn set_enum_default(&mut self, name: &str, option: &str) {
+        if let Some(ec) = self.db.enums.get_mut(name) {
+            if ec.default.as_deref() != Some(option) {
+                ec.default = Some(option.to_string());
+                self.dirty = true;
             }
         }
     }
@@ -439,6 +540,16 @@ impl TileBitsData {
             self.dirty = true;
         }
     }
+    pub fn set_dynamic(&mut self, word: &str, enum_name: &str, options: &BTreeSet<String>) {
+        let dd = ConfigDynamicData {
+            enum_name: enum_name.to_string(),
+            options: options.clone(),
+        };
+        if self.db.dynamic.get(word) != Some(&dd) {
+            self.db.dynamic.insert(word.to_string(), dd);
+            self.dirty = true;
+        }
+    }
 }
 
 pub struct Database {
@@ -616,6 +727,7 @@ impl Database {
                     enums: BTreeMap::new(),
                     conns: BTreeMap::new(),
                     always_on: BTreeSet::new(),
+                    dynamic: BTreeMap::new(),
                 }
             };
             self.tilebits
@@ -639,6 +751,7 @@ impl Database {
                     enums: BTreeMap::new(),
                     conns: BTreeMap::new(),
                     always_on: BTreeSet::new(),
+                    dynamic: BTreeMap::new(),
                 }
             };
             self.ipbits
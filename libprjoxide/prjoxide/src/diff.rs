@@ -0,0 +1,386 @@
+use crate::bitstream::*;
+use crate::chip::*;
+use crate::database::*;
+use crate::fasmparse::*;
+use num_bigint::BigInt as Integer;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::*;
+
+/*
+Feature level comparison of two chips
+
+Both chips are decoded to FASM features with the same matching as used by unpack,
+then compared feature by feature. Features are grouped by tile, by IP_ name for IP
+configuration, and optionally by bel tilegroup for enums and words that belong to a bel.
+
+The same comparison is used to verify that a FASM file survives packing and unpacking.
+The expected features come only from the FASM file and the bit database, canonicalised
+to what decoding can recover: tilegroups are expanded to their tiles, features that set
+no bits are dropped, and BASE_ enums are dropped where another feature overrides them.
+Enums whose declared default sets bits decode to their option that sets none when no
+bits of theirs are written, so that option is expected whether or not it was given.
+IP configuration, including memory contents, is also compared byte by byte against the
+bytes the FASM file requests.
+*/
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct FeatureDiff {
+    pub feature: String,
+    // None if the feature is only present in one of the chips
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+// Differences by tile, tilegroup or IP name
+pub type ChipDiff = BTreeMap<String, Vec<FeatureDiff>>;
+
+// Decoded features, keyed by feature name, of each group
+type DecodedChip = BTreeMap<String, BTreeMap<String, String>>;
+
+fn add_features(
+    decoded: &mut DecodedChip,
+    group: &str,
+    ft: &FasmTile,
+    bel_groups: &BTreeMap<(String, String), String>,
+) {
+    // Enums and words of a bel go to its tilegroup, if there is a mapping for it
+    let group_of = |feature: &str| {
+        feature
+            .split('.')
+            .next()
+            .and_then(|bel| bel_groups.get(&(group.to_string(), bel.to_string())))
+            .map_or(group, |g| g.as_str())
+            .to_string()
+    };
+    for (to_wire, from_wire) in ft.pips.iter() {
+        decoded
+            .entry(group.to_string())
+            .or_default()
+            .insert(format!("PIP.{}", to_wire), from_wire.to_string());
+    }
+    for (name, opt) in ft.enums.iter() {
+        decoded
+            .entry(group_of(name))
+            .or_default()
+            .insert(name.to_string(), opt.to_string());
+    }
+    for (name, value) in ft.words.iter() {
+        decoded
+            .entry(group_of(name))
+            .or_default()
+            .insert(name.to_string(), format!("0x{:X}", value));
+    }
+    for (f, b) in ft.unknowns.iter() {
+        decoded
+            .entry(group.to_string())
+            .or_default()
+            .insert(format!("UNKNOWN.{}.{}", f, b), "1".to_string());
+    }
+}
+
+fn decode_chip(chip: &Chip, db: &mut Database, by_bel: bool) -> DecodedChip {
+    let mut bel_groups = BTreeMap::new();
+    if by_bel {
+        // Map (tile, bel name) to the bel tilegroup. Chips from a bitstream
+        // don't have tilegroups, so create them on a copy if needed
+        let with_groups;
+        let ch = if chip.tilegroups.is_empty() {
+            let mut c = chip.clone();
+            c.create_tilegroups(db);
+            with_groups = c;
+            &with_groups
+        } else {
+            chip
+        };
+        for (group, tiles) in ch.tilegroups.iter() {
+            if let Some((_loc, bel)) = group.split_once('_') {
+                for tile in tiles.iter() {
+                    bel_groups.insert((tile.to_string(), bel.to_string()), group.to_string());
+                }
+            }
+        }
+    }
+    let mut decoded = DecodedChip::new();
+    for tile in chip.tiles.iter() {
+        add_features(&mut decoded, &tile.name, &tile.decode_fasm(db), &bel_groups);
+    }
+    for (ip, ft) in chip.decode_ip_fasm(db).iter() {
+        add_features(&mut decoded, ip, ft, &BTreeMap::new());
+    }
+    decoded
+}
+
+fn diff_decoded(dec_a: &DecodedChip, dec_b: &DecodedChip) -> ChipDiff {
+    let empty = BTreeMap::new();
+    let mut result = ChipDiff::new();
+    let groups: BTreeSet<&String> = dec_a.keys().chain(dec_b.keys()).collect();
+    for group in groups {
+        let fa = dec_a.get(group).unwrap_or(&empty);
+        let fb = dec_b.get(group).unwrap_or(&empty);
+        let features: BTreeSet<&String> = fa.keys().chain(fb.keys()).collect();
+        let diffs: Vec<FeatureDiff> = features
+            .into_iter()
+            .filter(|f| fa.get(*f) != fb.get(*f))
+            .map(|f| FeatureDiff {
+                feature: f.to_string(),
+                old: fa.get(f).cloned(),
+                new: fb.get(f).cloned(),
+            })
+            .collect();
+        if !diffs.is_empty() {
+            result.insert(group.to_string(), diffs);
+        }
+    }
+    result
+}
+
+// Compare two chips of the same device at feature level
+pub fn diff_chips(a: &Chip, b: &Chip, db: &mut Database, by_bel: bool) -> ChipDiff {
+    diff_decoded(&decode_chip(a, db, by_bel), &decode_chip(b, db, by_bel))
+}
+
+// Chip-wide settings that can be given as FASM attributes
+fn add_settings(decoded: &mut DecodedChip, chip: &Chip) {
+    let settings = decoded.entry("oxide".to_string()).or_default();
+    settings.insert("usercode".to_string(), format!("0x{:08X}", chip.usercode));
+    settings.insert("ctrl0".to_string(), format!("0x{:08X}", chip.ctrl0));
+    settings.insert("power_ctrl".to_string(), format!("0x{:02X}", chip.power_ctrl));
+    for key in ["sed_crc", "secure"].iter() {
+        if let Some(value) = chip.settings.get(*key) {
+            settings.insert(key.to_string(), value.to_string());
+        }
+    }
+}
+
+// The settings requested by the attributes of a FASM file, starting from the defaults
+// of a new chip
+fn fasm_settings(decoded: &mut DecodedChip, fasm: &ParsedFasm, layout: &Chip) {
+    add_settings(decoded, layout);
+    let settings = decoded.entry("oxide".to_string()).or_default();
+    for (k, v) in fasm.attrs.iter() {
+        let (key, width) = match k.as_str() {
+            "oxide.usercode" => ("usercode", 8),
+            "oxide.ctrl0" => ("ctrl0", 8),
+            "oxide.power_ctrl" => ("power_ctrl", 2),
+            "oxide.secure" => {
+                settings.insert("secure".to_string(), v.to_string());
+                continue;
+            }
+            _ => continue,
+        };
+        if let Some(x) = parse_attr_int(v) {
+            settings.insert(key.to_string(), format!("0x{:0width$X}", x, width = width));
+        }
+    }
+}
+
+// IP configuration bytes, by address
+fn add_ip_bytes(decoded: &mut DecodedChip, ipconfig: &BTreeMap<u32, u8>) {
+    let bytes = decoded.entry("ipconfig".to_string()).or_default();
+    for (addr, val) in ipconfig.iter() {
+        bytes.insert(format!("0x{:08X}", addr), format!("0x{:02X}", val));
+    }
+}
+
+// True if setting a word to a value leaves all of its bits cleared
+fn word_sets_no_bits(wdata: &ConfigWordData, value: &Integer) -> bool {
+    wdata
+        .bits
+        .iter()
+        .enumerate()
+        .all(|(i, wb)| wb.iter().all(|cb| cb.invert == value.bit(i as u64)))
+}
+
+// True if an enum option or pip leaves all of its bits cleared
+fn sets_no_bits(bits: &BTreeSet<ConfigBit>) -> bool {
+    bits.iter().all(|cb| cb.invert)
+}
+
+// The option that an enum with no bits written decodes to, if not its default. This is
+// only known when the default sets bits and exactly one option sets none
+fn cleared_option(edata: &ConfigEnumData) -> Option<&str> {
+    let default = edata.default.as_ref().and_then(|d| edata.options.get(d))?;
+    if sets_no_bits(default) {
+        return None;
+    }
+    let mut cleared = edata.options.iter().filter(|(_k, bits)| sets_no_bits(bits));
+    match (cleared.next(), cleared.next()) {
+        (Some((opt, _bits)), None) => Some(opt.as_str()),
+        _ => None,
+    }
+}
+
+// Add the options that enums decode to when no feature writes any of their bits
+fn add_cleared_options(features: &mut BTreeMap<String, String>, tdb: &TileBitsDatabase, written: &BTreeSet<(usize, usize)>) {
+    for (name, edata) in tdb.enums.iter() {
+        let opt = match cleared_option(edata) {
+            Some(opt) => opt,
+            None => continue,
+        };
+        let any_written = edata.options.values().flatten().any(|cb| written.contains(&(cb.frame, cb.bit)));
+        if !any_written && !features.contains_key(name) {
+            features.insert(name.to_string(), opt.to_string());
+        }
+    }
+}
+
+// The bits of a tile that are written by FASM features other than BASE_ enums
+fn written_bits(fts: &[(&FasmTile, bool)], tdb: &TileBitsDatabase) -> BTreeSet<(usize, usize)> {
+    let mut written = BTreeSet::new();
+    for (ft, is_group) in fts.iter() {
+        for (name, opt) in ft.enums.iter().filter(|(k, _)| !k.starts_with("BASE_")) {
+            if let Some(bits) = tdb.enums.get(name).and_then(|e| e.options.get(opt)) {
+                written.extend(bits.iter().map(|cb| (cb.frame, cb.bit)));
+            }
+        }
+        for name in ft.words.keys() {
+            if let Some(wdata) = tdb.words.get(name) {
+                written.extend(wdata.bits.iter().flatten().map(|cb| (cb.frame, cb.bit)));
+            }
+        }
+        if *is_group {
+            continue;
+        }
+        for (to_wire, from_wire) in ft.pips.iter() {
+            if let Some(p) = tdb.pips.get(to_wire).and_then(|pips| pips.iter().find(|p| &p.from_wire == from_wire)) {
+                written.extend(p.bits.iter().map(|cb| (cb.frame, cb.bit)));
+            }
+        }
+        written.extend(ft.unknowns.iter().cloned());
+    }
+    written
+}
+
+// Add the features of a FASM tile that set bits in a tile or IP block. Enums and
+// words are only added if the tile database has them, so tilegroups can be applied to
+// each of their tiles. Bits written by other features are given as written, and
+// BASE_ enums are dropped if another feature overrides any of their bits.
+fn add_expected(
+    decoded: &mut DecodedChip,
+    group: &str,
+    ft: &FasmTile,
+    tdb: &TileBitsDatabase,
+    written: &BTreeSet<(usize, usize)>,
+) {
+    let features = decoded.entry(group.to_string()).or_default();
+    for (name, opt) in ft.enums.iter() {
+        let edata = match tdb.enums.get(name) {
+            Some(edata) => edata,
+            None => continue,
+        };
+        let bits = match edata.options.get(opt) {
+            Some(bits) if !sets_no_bits(bits) || cleared_option(edata) == Some(opt) => bits,
+            _ => continue,
+        };
+        let overridden = bits.iter().any(|cb| written.contains(&(cb.frame, cb.bit)));
+        if !(name.starts_with("BASE_") && overridden) {
+            features.insert(name.to_string(), opt.to_string());
+        }
+    }
+    for (name, value) in ft.words.iter() {
+        if tdb.words.get(name).is_some_and(|w| !word_sets_no_bits(w, value)) {
+            features.insert(name.to_string(), format!("0x{:X}", value));
+        }
+    }
+}
+
+// Canonicalise the features of a FASM file to those decoding can recover. Only the
+// FASM file and the bit database are used; the layout chip is a new chip of the device,
+// with tilegroups, that gives the tiles, tilegroups and IP addresses.
+fn fasm_features(fasm: &ParsedFasm, layout: &Chip, db: &mut Database) -> DecodedChip {
+    let mut expected = DecodedChip::new();
+    fasm_settings(&mut expected, fasm, layout);
+    // The FASM tiles applied to each tile, in the order that Chip::from_fasm applies them,
+    // and whether each came from a tilegroup
+    let mut by_tile = BTreeMap::<String, Vec<(&FasmTile, bool)>>::new();
+    // IP configuration as requested by the FASM file
+    let mut requested = layout.clone();
+    for (name, ft) in fasm.tiles.iter() {
+        if let Some(ip) = name.strip_prefix("IP_") {
+            // Errors have already been reported when creating the chip
+            let _ = requested.configure_ip(ip, db, ft);
+            // Memories and IP without a bit database are only compared as bytes
+            if let Some(iptype @ ("PLL_CORE" | "DPHY_CORE" | "PCIE_CORE")) = Chip::ip_type_by_name(ip) {
+                let tdb = &db.ip_bitdb(&layout.family, iptype).db;
+                add_expected(&mut expected, name, ft, tdb, &BTreeSet::new());
+                let written = written_bits(&[(ft, false)], tdb);
+                add_cleared_options(expected.entry(name.to_string()).or_default(), tdb, &written);
+            }
+            continue;
+        }
+        match layout.tilegroups.get(name) {
+            Some(tiles) => {
+                for tile in tiles.iter() {
+                    by_tile.entry(tile.to_string()).or_default().push((ft, true));
+                }
+            }
+            None => by_tile.entry(name.to_string()).or_default().push((ft, false)),
+        }
+    }
+    add_ip_bytes(&mut expected, &requested.ipconfig);
+    // Every tile is decoded, so enums of tiles without features can be expected too
+    for t in layout.tiles.iter() {
+        let tile = &t.name;
+        let fts = by_tile.get(tile).map_or(&[][..], |fts| fts.as_slice());
+        let tdb = &db.tile_bitdb(&layout.family, &t.tiletype).db;
+        let written = written_bits(fts, tdb);
+        for (ft, is_group) in fts.iter() {
+            add_expected(&mut expected, tile, ft, tdb, &written);
+            if *is_group {
+                continue;
+            }
+            let features = expected.entry(tile.to_string()).or_default();
+            for (to_wire, from_wire) in ft.pips.iter() {
+                // Fixed connections have no bits, so are not recovered
+                let sets_bits = tdb.pips.get(to_wire).is_some_and(|pips| {
+                    pips.iter().any(|p| &p.from_wire == from_wire && !sets_no_bits(&p.bits))
+                });
+                if sets_bits {
+                    features.insert(format!("PIP.{}", to_wire), from_wire.to_string());
+                }
+            }
+            for (f, b) in ft.unknowns.iter() {
+                features.insert(format!("UNKNOWN.{}.{}", f, b), "1".to_string());
+            }
+        }
+        add_cleared_options(expected.entry(tile.to_string()).or_default(), tdb, &written);
+    }
+    expected.retain(|_k, v| !v.is_empty());
+    expected
+}
+
+// Pack a chip created from a FASM file to a bitstream, unpack it again and compare the
+// result with the features of the FASM file. Differences are from the FASM file to the
+// unpacked bitstream, so features that were lost are removed and UNKNOWN bits are added
+pub fn verify_fasm(fasm: &ParsedFasm, chip: &Chip, db: &mut Database) -> std::result::Result<ChipDiff, BitstreamError> {
+    let mut layout = Chip::from_name_variant(db, &chip.device, &chip.variant);
+    layout.create_tilegroups(db);
+    let expected = fasm_features(fasm, &layout, db);
+    let bitstream = BitstreamParser::serialise_chip(chip)?;
+    let mut unpacked = BitstreamParser::new(&bitstream).parse(db)?;
+    unpacked.cram_to_tiles();
+    let mut actual = decode_chip(&unpacked, db, false);
+    // IP configuration is compared byte by byte rather than as IP_UNKNOWN
+    actual.remove("IP_UNKNOWN");
+    add_ip_bytes(&mut actual, &unpacked.ipconfig);
+    add_settings(&mut actual, &unpacked);
+    actual.retain(|_k, v| !v.is_empty());
+    Ok(diff_decoded(&expected, &actual))
+}
+
+// Print a diff, with one line per feature: + added, - removed, ~ changed
+pub fn write_diff(out: &mut dyn Write, diff: &ChipDiff) -> Result<()> {
+    for (group, diffs) in diff.iter() {
+        writeln!(out, "{}:", group)?;
+        for d in diffs.iter() {
+            match (&d.old, &d.new) {
+                (None, Some(n)) => writeln!(out, "  + {} = {}", d.feature, n)?,
+                (Some(o), None) => writeln!(out, "  - {} = {}", d.feature, o)?,
+                (Some(o), Some(n)) => writeln!(out, "  ~ {}: {} -> {}", d.feature, o, n)?,
+                (None, None) => {}
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
 use std::convert::TryInto;
+use std::fmt;
 use std::fs::File;
 use std::io::*;
 
@@ -23,6 +24,12 @@ pub struct FasmTile {
     pub enums: BTreeMap<String, String>,
     pub words: BTreeMap<String, Integer>,
     pub unknowns: Vec<(usize, usize)>,
+    // Line of the first feature in this tile
+    pub line: usize,
+    // Line of each enum, word, pip (keyed as PIP.to_wire) and unknown (as UNKNOWN.frame.bit)
+    pub lines: BTreeMap<String, usize>,
+    // Problems found when decoding from a bitstream, such as ambiguous features
+    pub warnings: Vec<String>,
 }
 
 impl FasmTile {
@@ -32,12 +39,61 @@ impl FasmTile {
             enums: BTreeMap::new(),
             words: BTreeMap::new(),
             unknowns: Vec::new(),
+            line: 0,
+            lines: BTreeMap::new(),
+            warnings: Vec::new(),
         }
     }
+    // Get the source line of a feature, falling back to the first line of the tile
+    pub fn line_of(&self, key: &str) -> usize {
+        *self.lines.get(key).unwrap_or(&self.line)
+    }
+}
+
+// An error applying a FASM feature to the chip, with its source line if known
+#[derive(Debug, Clone, PartialEq)]
+pub struct FasmError {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl FasmError {
+    pub fn new(line: usize, message: String) -> FasmError {
+        FasmError {
+            line: if line > 0 { Some(line) } else { None },
+            message,
+        }
+    }
+}
+
+impl fmt::Display for FasmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(l) => write!(f, "line {}: {}", l, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for FasmError {}
+
+// Parse an integer-valued attribute, such as `oxide.usercode`
+// Decimal, 0x-prefixed hex and 0b-prefixed binary are accepted
+pub fn parse_attr_int(value: &str) -> Option<u64> {
+    let v = value.trim().replace('_', "");
+    if let Some(hex) = v.strip_prefix("0x").or_else(|| v.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = v.strip_prefix("0b").or_else(|| v.strip_prefix("0B")) {
+        u64::from_str_radix(bin, 2).ok()
+    } else {
+        v.parse::<u64>().ok()
+    }
 }
 
 pub struct ParsedFasm {
     pub attrs: Vec<(String, String)>,
+    // Line of each attribute, by key
+    pub attr_lines: BTreeMap<String, usize>,
     pub tiles: BTreeMap<String, FasmTile>,
 }
 
@@ -45,6 +101,7 @@ impl ParsedFasm {
     pub fn parse(filename: &str) -> Result<ParsedFasm> {
         let mut p = ParsedFasm {
             attrs: Vec::new(),
+            attr_lines: BTreeMap::new(),
             tiles: BTreeMap::new(),
         };
         let file = File::open(filename)?;
@@ -154,6 +211,7 @@ impl ParsedFasm {
                     skip_whitespace(&mut buf);
                     assert_token(&mut buf, "=");
                     skip_whitespace(&mut buf);
+                    p.attr_lines.insert(key.clone(), lineno + 1);
                     p.attrs.push((key, get_attr_value(&mut buf)));
                     skip_whitespace(&mut buf);
                     if !check_token(&mut buf, ",") {
@@ -164,17 +222,22 @@ impl ParsedFasm {
             } else {
                 let tilename = get_ident(&mut buf).replace("__", ":");
                 let tile_data = p.tiles.entry(tilename).or_insert_with(FasmTile::new);
+                if tile_data.line == 0 {
+                    tile_data.line = lineno + 1;
+                }
                 assert_token(&mut buf, ".");
                 if check_token(&mut buf, "PIP.") {
                     // It's a pip
                     let to_wire = get_ident(&mut buf).replace("__", ":");
                     assert_token(&mut buf, ".");
                     let from_wire = get_ident(&mut buf).replace("__", ":");
+                    tile_data.lines.insert(format!("PIP.{}", to_wire), lineno + 1);
                     tile_data.pips.insert(to_wire, from_wire);
                 } else if check_token(&mut buf, "UNKNOWN.") {
                     let frame: usize = get_integer(&mut buf).try_into().unwrap();
                     assert_token(&mut buf, ".");
                     let bit: usize = get_integer(&mut buf).try_into().unwrap();
+                    tile_data.lines.insert(format!("UNKNOWN.{}.{}", frame, bit), lineno + 1);
                     tile_data.unknowns.push((frame, bit));
                 } else {
                     let mut feature_split = Vec::new();
@@ -207,6 +270,7 @@ impl ParsedFasm {
                         } else {
                             Integer::from(1)
                         };
+                        tile_data.lines.insert(key.clone(), lineno + 1);
                         let dest = tile_data
                             .words
                             .entry(key)
@@ -219,6 +283,7 @@ impl ParsedFasm {
                         // Enum style setting
                         let key = feature_split[0..feature_split.len() - 1].join(".");
                         let value = &feature_split[feature_split.len() - 1];
+                        tile_data.lines.insert(key.clone(), lineno + 1);
                         tile_data.enums.insert(key, value.to_string());
                     }
                 }
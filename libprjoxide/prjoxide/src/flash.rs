@@ -0,0 +1,193 @@
+use crate::bitstream::BitstreamParser;
+use crate::database::Database;
+
+/*
+Assembly of multi-image SPI flash images, for dual boot and multiboot
+
+The flash is laid out as follows:
+ - a jump table at address 0, which switches the SPI read mode and jumps to the
+   first primary image
+ - the golden image, which is booted if the primary image fails to configure
+ - one or more primary images; any after the first can be selected at runtime
+   using the MULTIBOOT primitive
+
+All images must start on a sector boundary, and unused space is filled with 0xFF
+(the erased state of the flash).
+
+Images are parsed before they are added, so a file that is not a bitstream, or is for
+a different device to the golden image, can't end up on the boot path.
+*/
+
+// Default flash sector size
+pub const DEFAULT_FLASH_ALIGN: u32 = 0x10000;
+
+// Default SPI read mode (standard 0x03 read)
+pub const DEFAULT_SPI_MODE: u8 = 0x03;
+
+pub struct FlashImage {
+    pub name: String,
+    pub offset: u32,
+    pub data: Vec<u8>,
+}
+
+impl FlashImage {
+    pub fn new(name: &str, offset: u32, data: Vec<u8>) -> FlashImage {
+        FlashImage {
+            name: name.to_string(),
+            offset,
+            data,
+        }
+    }
+    fn end(&self) -> u64 {
+        self.offset as u64 + self.data.len() as u64
+    }
+    // Check the image is a complete bitstream, returning its IDCODE
+    fn check_bitstream(&self, db: &mut Database) -> Result<u32, String> {
+        if !self.data.starts_with(b"LSCC") {
+            return Err(format!("{} is not a bitstream (no LSCC header)", self.name));
+        }
+        let mut parser = BitstreamParser::new(&self.data);
+        let chip = parser
+            .parse(db)
+            .map_err(|e| format!("{} is not a valid bitstream: {}", self.name, e))?;
+        if !parser.commands().iter().any(|cmd| cmd.name == "ISC_PROGRAM_DONE") {
+            return Err(format!("{} is incomplete (no ISC_PROGRAM_DONE)", self.name));
+        }
+        Ok(chip.get_idcode())
+    }
+}
+
+// Check that the golden and primary images are valid bitstreams for the same device
+pub fn check_images(db: &mut Database, golden: &FlashImage, primaries: &[FlashImage]) -> Result<(), String> {
+    let idcode = golden.check_bitstream(db)?;
+    for img in primaries.iter() {
+        let img_idcode = img.check_bitstream(db)?;
+        if img_idcode != idcode {
+            return Err(format!(
+                "{} has IDCODE 0x{:08X}, but {} has IDCODE 0x{:08X}",
+                img.name, img_idcode, golden.name, idcode
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Build a flash image from a golden and one or more primary bitstreams
+pub fn build_flash(
+    golden: &FlashImage,
+    primaries: &[FlashImage],
+    align: u32,
+    spi_mode: u8,
+) -> Result<Vec<u8>, String> {
+    if align == 0 {
+        return Err("flash alignment must be non-zero".to_string());
+    }
+    if primaries.is_empty() {
+        return Err("at least one primary image is required".to_string());
+    }
+    let jump = FlashImage::new(
+        "jump table",
+        0,
+        BitstreamParser::serialise_jump(spi_mode, primaries[0].offset),
+    );
+    let mut images = vec![&jump, golden];
+    images.extend(primaries.iter());
+    for img in images.iter().skip(1) {
+        if img.offset % align != 0 {
+            return Err(format!(
+                "{} offset 0x{:08X} is not aligned to 0x{:X}",
+                img.name, img.offset, align
+            ));
+        }
+        if img.data.is_empty() {
+            return Err(format!("{} is empty", img.name));
+        }
+    }
+    images.sort_by_key(|img| img.offset);
+    for pair in images.windows(2) {
+        if pair[0].end() > pair[1].offset as u64 {
+            return Err(format!(
+                "{} (0x{:08X}..0x{:08X}) overlaps {} at 0x{:08X}",
+                pair[0].name,
+                pair[0].offset,
+                pair[0].end(),
+                pair[1].name,
+                pair[1].offset
+            ));
+        }
+    }
+    let total = images.iter().map(|img| img.end()).max().unwrap_or(0);
+    if total > (1u64 << 32) {
+        return Err("flash image exceeds 32-bit address space".to_string());
+    }
+    let mut flash = vec![0xFF; total as usize];
+    for img in images.iter() {
+        let start = img.offset as usize;
+        flash[start..start + img.data.len()].copy_from_slice(&img.data);
+    }
+    Ok(flash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(name: &str, offset: u32, len: usize, fill: u8) -> FlashImage {
+        FlashImage::new(name, offset, vec![fill; len])
+    }
+
+    #[test]
+    fn jump_table_encoding() {
+        let jump = BitstreamParser::serialise_jump(0x0B, 0x0012_3400);
+        let mut expected = vec![0xFF, 0xFF, 0xBD, 0xB3, 0xFF, 0xFF, 0xFF, 0xFF];
+        // SPI_MODE with the read opcode
+        expected.extend([0x79, 0x0B, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF].iter());
+        // JUMP with the big-endian flash address
+        expected.extend([0x7E, 0x00, 0x00, 0x00, 0x00, 0x12, 0x34, 0x00].iter());
+        expected.extend([0xFF; 16].iter());
+        assert_eq!(jump, expected);
+    }
+
+    #[test]
+    fn layout() {
+        let golden = image("golden", 0x100, 0x20, 0xA5);
+        let primaries = [image("primary 0", 0x300, 0x10, 0x5A), image("primary 1", 0x200, 0x08, 0x3C)];
+        let flash = build_flash(&golden, &primaries, 0x100, DEFAULT_SPI_MODE).unwrap();
+        // The flash ends at the end of the last image
+        assert_eq!(flash.len(), 0x310);
+        // The jump table goes to the first primary image, not the lowest
+        let jump = BitstreamParser::serialise_jump(DEFAULT_SPI_MODE, 0x300);
+        assert_eq!(&flash[..jump.len()], &jump[..]);
+        assert!(flash[jump.len()..0x100].iter().all(|&b| b == 0xFF));
+        assert!(flash[0x100..0x120].iter().all(|&b| b == 0xA5));
+        assert!(flash[0x120..0x200].iter().all(|&b| b == 0xFF));
+        assert!(flash[0x200..0x208].iter().all(|&b| b == 0x3C));
+        assert!(flash[0x208..0x300].iter().all(|&b| b == 0xFF));
+        assert!(flash[0x300..].iter().all(|&b| b == 0x5A));
+    }
+
+    #[test]
+    fn layout_errors() {
+        let golden = image("golden", 0x100, 0x20, 0xA5);
+        let primary = image("primary 0", 0x200, 0x10, 0x5A);
+        let err = |golden: &FlashImage, primaries: &[FlashImage], align| {
+            build_flash(golden, primaries, align, DEFAULT_SPI_MODE).unwrap_err()
+        };
+        assert_eq!(err(&golden, &[], 0x100), "at least one primary image is required");
+        assert_eq!(err(&golden, &[image("primary 0", 0x200, 0x10, 0)], 0), "flash alignment must be non-zero");
+        assert_eq!(
+            err(&golden, &[image("primary 0", 0x280, 0x10, 0)], 0x100),
+            "primary 0 offset 0x00000280 is not aligned to 0x100"
+        );
+        assert_eq!(err(&golden, &[image("primary 0", 0x200, 0, 0)], 0x100), "primary 0 is empty");
+        assert_eq!(
+            err(&image("golden", 0x100, 0x101, 0), &[primary], 0x100),
+            "golden (0x00000100..0x00000201) overlaps primary 0 at 0x00000200"
+        );
+        // The golden image can't overwrite the jump table
+        assert_eq!(
+            err(&image("golden", 0, 0x20, 0), &[image("primary 0", 0x200, 0x10, 0)], 0x100),
+            "jump table (0x00000000..0x00000028) overlaps golden at 0x00000000"
+        );
+    }
+}
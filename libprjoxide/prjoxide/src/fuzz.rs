@@ -378,6 +378,10 @@ impl Fuzzer {
                                     let tile_db =
                                         db.tile_bitdb(&self.base.family, &tile_data.tiletype);
                                     tile_db.add_enum_option(name, &option, &self.desc, b);
+                                    // An option with no changes in this tile is the one the base design is in
+                                    if delta.get(&tile).is_none() {
+                                        tile_db.set_enum_default(name, option);
+                                    }
                                 }
                             }
                         }
@@ -416,6 +420,9 @@ pub fn copy_db(
                     for (opt, bits) in opts.options.iter() {
                         dest_data.add_enum_option(name, opt, &opts.desc, bits.clone());
                     }
+                    if let Some(default) = opts.default.as_ref() {
+                        dest_data.set_enum_default(name, default);
+                    }
                 }
             }
         }
@@ -513,3 +520,21 @@ pub fn add_always_on_bits(
     }
     db.flush();
 }
+
+// Mark a word as dynamic (changing at runtime, like LUT RAM initialisation)
+// so it is masked from frame ECC, optionally only when an enum has one of the given options
+pub fn set_dynamic_word(
+    db: &mut Database,
+    family: &str,
+    tiletype: &str,
+    word: &str,
+    enum_name: &str,
+    options: &BTreeSet<String>,
+) {
+    let tdb = db.tile_bitdb(family, tiletype);
+    if !tdb.db.words.contains_key(word) {
+        panic!("no word named {} in tile type {}", word, tiletype);
+    }
+    tdb.set_dynamic(word, enum_name, options);
+    db.flush();
+}
@@ -0,0 +1,208 @@
+use std::io::*;
+use std::str::FromStr;
+
+/*
+Writers for the file formats used by flash programmers
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    // Plain binary, as written by serialise_chip
+    Raw,
+    // Intel HEX, with extended linear address records
+    IntelHex,
+    // Motorola S-record, with 32-bit addresses (S3/S7)
+    SRecord,
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "raw" | "bin" | "bit" => Ok(ImageFormat::Raw),
+            "ihex" | "hex" => Ok(ImageFormat::IntelHex),
+            "srec" | "mot" => Ok(ImageFormat::SRecord),
+            _ => Err(format!(
+                "unknown image format '{}' (expected raw, ihex or srec)",
+                s
+            )),
+        }
+    }
+}
+
+// Data bytes per record
+const RECORD_LEN: usize = 16;
+
+// Write an image at a base address, optionally reversing the bits in each byte
+// (some programmers expect flash data LSB first)
+pub fn write_image(
+    out: &mut dyn Write,
+    data: &[u8],
+    format: ImageFormat,
+    base_addr: u32,
+    bit_reverse: bool,
+) -> Result<()> {
+    if base_addr as u64 + data.len() as u64 > (1u64 << 32) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "image exceeds 32-bit address space",
+        ));
+    }
+    let data: Vec<u8> = if bit_reverse {
+        data.iter().map(|b| b.reverse_bits()).collect()
+    } else {
+        data.to_vec()
+    };
+    match format {
+        ImageFormat::Raw => out.write_all(&data),
+        ImageFormat::IntelHex => write_ihex(out, &data, base_addr),
+        ImageFormat::SRecord => write_srec(out, &data, base_addr),
+    }
+}
+
+fn write_ihex_record(out: &mut dyn Write, addr: u16, rectype: u8, data: &[u8]) -> Result<()> {
+    let mut rec = vec![data.len() as u8, (addr >> 8) as u8, (addr & 0xFF) as u8, rectype];
+    rec.extend_from_slice(data);
+    let sum = rec.iter().fold(0u8, |acc, x| acc.wrapping_add(*x));
+    rec.push(0u8.wrapping_sub(sum));
+    write!(out, ":")?;
+    for b in rec.iter() {
+        write!(out, "{:02X}", b)?;
+    }
+    writeln!(out)
+}
+
+fn write_ihex(out: &mut dyn Write, data: &[u8], base_addr: u32) -> Result<()> {
+    let mut upper: Option<u16> = None;
+    let mut addr = base_addr;
+    let mut remaining = data;
+    while !remaining.is_empty() {
+        // Records must not cross a 64k boundary
+        let to_boundary = 0x10000 - (addr & 0xFFFF) as usize;
+        let len = std::cmp::min(std::cmp::min(RECORD_LEN, to_boundary), remaining.len());
+        let addr_upper = (addr >> 16) as u16;
+        if upper != Some(addr_upper) {
+            write_ihex_record(out, 0, 0x04, &addr_upper.to_be_bytes())?;
+            upper = Some(addr_upper);
+        }
+        write_ihex_record(out, (addr & 0xFFFF) as u16, 0x00, &remaining[..len])?;
+        addr = addr.wrapping_add(len as u32);
+        remaining = &remaining[len..];
+    }
+    write_ihex_record(out, 0, 0x01, &[])
+}
+
+fn write_srec_record(out: &mut dyn Write, rectype: char, addr: &[u8], data: &[u8]) -> Result<()> {
+    let mut rec = vec![(addr.len() + data.len() + 1) as u8];
+    rec.extend_from_slice(addr);
+    rec.extend_from_slice(data);
+    let sum = rec.iter().fold(0u8, |acc, x| acc.wrapping_add(*x));
+    rec.push(!sum);
+    write!(out, "S{}", rectype)?;
+    for b in rec.iter() {
+        write!(out, "{:02X}", b)?;
+    }
+    writeln!(out)
+}
+
+fn write_srec(out: &mut dyn Write, data: &[u8], base_addr: u32) -> Result<()> {
+    write_srec_record(out, '0', &[0, 0], b"prjoxide")?;
+    let mut count = 0;
+    for (i, chunk) in data.chunks(RECORD_LEN).enumerate() {
+        let addr = base_addr + (i * RECORD_LEN) as u32;
+        write_srec_record(out, '3', &addr.to_be_bytes(), chunk)?;
+        count += 1;
+    }
+    // Record count, if it fits in S5 (or S6 for 24-bit counts)
+    if count <= 0xFFFF {
+        write_srec_record(out, '5', &(count as u16).to_be_bytes(), &[])?;
+    } else if count <= 0xFFFFFF {
+        write_srec_record(out, '6', &(count as u32).to_be_bytes()[1..], &[])?;
+    }
+    write_srec_record(out, '7', &base_addr.to_be_bytes(), &[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Expected records are from `objcopy -I binary -O ihex` / `-O srec --srec-forceS3`
+    // with `--change-addresses`, less the start address and S0 header records objcopy adds
+
+    fn image(data: &[u8], format: ImageFormat, base_addr: u32, bit_reverse: bool) -> Vec<String> {
+        let mut out = Vec::new();
+        write_image(&mut out, data, format, base_addr, bit_reverse).unwrap();
+        String::from_utf8(out).unwrap().lines().map(str::to_string).collect()
+    }
+
+    fn test_data() -> Vec<u8> {
+        (0x00..0x14).collect()
+    }
+
+    #[test]
+    fn ihex_extended_address() {
+        // Crosses a 64k boundary, so needs a second type-04 record
+        assert_eq!(
+            image(&test_data(), ImageFormat::IntelHex, 0x0101_FFF8, false),
+            [
+                ":020000040101F8",
+                ":08FFF8000001020304050607E5",
+                ":020000040102F7",
+                ":0C00000008090A0B0C0D0E0F1011121352",
+                ":00000001FF",
+            ]
+        );
+    }
+
+    #[test]
+    fn ihex_bit_reverse() {
+        assert_eq!(
+            image(&test_data(), ImageFormat::IntelHex, 0x0101_FFF8, true),
+            [
+                ":020000040101F8",
+                ":08FFF800008040C020A060E081",
+                ":020000040102F7",
+                ":0C000000109050D030B070F0088848C854",
+                ":00000001FF",
+            ]
+        );
+    }
+
+    #[test]
+    fn srec_records() {
+        // S3 is used even when the addresses would fit in S2
+        assert_eq!(
+            image(&test_data(), ImageFormat::SRecord, 0x0001_FFF8, false),
+            [
+                "S00B000070726A6F786964658F",
+                "S3150001FFF8000102030405060708090A0B0C0D0E0F7A",
+                "S3090002000810111213A6",
+                "S5030002FA",
+                "S7050001FFF802",
+            ]
+        );
+    }
+
+    #[test]
+    fn address_space_bound() {
+        // Ending exactly at 4G is allowed, one byte further is not
+        assert_eq!(
+            image(&test_data(), ImageFormat::SRecord, 0xFFFF_FFEC, false)[1..3],
+            [
+                "S315FFFFFFEC000102030405060708090A0B0C0D0E0F89",
+                "S309FFFFFFFC10111213B7",
+            ]
+        );
+        for &format in [ImageFormat::Raw, ImageFormat::IntelHex, ImageFormat::SRecord].iter() {
+            let err = write_image(&mut Vec::new(), &test_data(), format, 0xFFFF_FFED, false).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn raw_bit_reverse() {
+        let mut out = Vec::new();
+        write_image(&mut out, &[0x01, 0x80, 0x0F, 0xA5], ImageFormat::Raw, 0, true).unwrap();
+        assert_eq!(out, [0x80, 0x01, 0xF0, 0xA5]);
+    }
+}
@@ -7,7 +7,7 @@ use std::convert::TryInto;
 
 use crate::bba::idstring::*;
 use crate::bba::idxset::*;
-use crate::bba::tiletype::{Neighbour, BranchSide, TileTypes};
+use crate::bba::tiletype::{Neighbour, TileTypes};
 
 use crate::sites::*;
 use crate::wires::*;
@@ -352,39 +352,14 @@ impl <'a> GraphBuilder<'a> {
     }
     // Convert a neighbour to a coordinate
     pub fn neighbour_tile(&self, x: u32, y: u32, n: &Neighbour) -> Option<(u32, u32)> {
-        let conv_tuple = |(x, y)| (x as u32, y as u32);
         match n {
-            Neighbour::RelXY { rel_x, rel_y } => {
-                let nx = (x as i32) + rel_x;
-                let ny = (y as i32) + rel_y;
-                if nx >= 0 && ny >= 0 && (nx as u32) < self.g.width && (ny as u32) < self.g.height {
-                    Some((nx as u32, ny as u32))
-                } else {
-                    None
-                }
-            }
             Neighbour::Global => {
                 // FIXME: current interchange format assumption that (0, 0) is empty
                 Some((1, 1))
             }
-            Neighbour::Branch => {
-                let branch_col = self.glb.branch_sink_to_origin(x as usize).unwrap();
-                Some((branch_col.try_into().unwrap(), y))
-            }
-            Neighbour::BranchDriver { side } => {
-                let offset: i32 = match side {
-                    BranchSide::Right => 2,
-                    BranchSide::Left => -2,
-                };
-                let branch_col = self
-                    .glb
-                    .branch_sink_to_origin((x as i32 + offset) as usize)
-                    .unwrap();
-                Some((branch_col.try_into().unwrap(), y))
-            }
-            Neighbour::Spine => Some(conv_tuple(self.glb.spine_sink_to_origin(x as usize, y as usize).unwrap())),
-            Neighbour::HRow => Some(conv_tuple(self.glb.hrow_sink_to_origin(x as usize, y as usize).unwrap())),
-            _ => None
+            _ => n
+                .resolve(&self.glb, x as usize, y as usize, self.g.width as usize, self.g.height as usize)
+                .map(|(nx, ny)| (nx as u32, ny as u32)),
         }
     }
     fn setup_wire2node(&mut self) {
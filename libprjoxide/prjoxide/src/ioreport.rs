@@ -0,0 +1,212 @@
+use crate::chip::*;
+use crate::database::*;
+use crate::fasmparse::*;
+use crate::pinout::pin_sort_key;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+/*
+Per-pin IO configuration report
+
+Each bonded pin of a package is mapped to its pad in the IO database, and from there
+to the PIO bel at the edge of the device (the same mapping nextpnr uses). The enums of
+the bel (PIOx.*, PIOx.SEIO18.* and PIOx.DIFFIO18.*) are decoded from the tiles of its
+tilegroup, and the IO type, drive, slew, pull mode and termination read from them.
+
+Drive and termination are split by voltage in the bit database (e.g. DRIVE_3V3 or
+TERMINATION_1V8), so the enum matching the voltage of the IO type is used.
+*/
+
+struct DecodedTile {
+    fasm: FasmTile,
+    // Default option of each enum that has one
+    defaults: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct PinConfig {
+    pub pin: String,
+    pub pad: String,
+    pub bank: Option<i32>,
+    pub functions: Vec<String>,
+    // Name of the PIO bel tilegroup, e.g. R3C87_PIOA
+    pub bel: Option<String>,
+    // None if the pin is unused
+    pub iotype: Option<String>,
+    pub direction: Option<String>,
+    pub drive: Option<String>,
+    pub slew: Option<String>,
+    pub pull: Option<String>,
+    pub termination: Option<String>,
+}
+
+// Tilegroup of the PIO bel of a pad
+fn pad_bel(chip: &Chip, pad: &PadData) -> Option<String> {
+    let (x, y) = match pad.side.as_str() {
+        "L" => (0, pad.offset),
+        "R" => (chip.data.max_col as i32, pad.offset),
+        "T" => (pad.offset, 0),
+        "B" => (pad.offset, chip.data.max_row as i32),
+        _ => return None,
+    };
+    if pad.pio < 0 {
+        return None;
+    }
+    Some(format!("R{}C{}_PIO{}", y, x, (b'A' + pad.pio as u8) as char))
+}
+
+// Voltage of an IO type, as used in enum names: LVCMOS33 gives 3V3, SSTL135_I gives 1V35
+fn iotype_voltage(iostd: &str) -> Option<String> {
+    let digits: String = iostd
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    if digits.len() < 2 {
+        return None;
+    }
+    Some(format!("{}V{}", &digits[..1], &digits[1..]))
+}
+
+// Settings of a PIO bel, from the decoded enums of its tiles, falling back to the
+// declared default (or else the no bits set) option of enums that were not decoded
+fn pin_settings(bel: &str, tiles: &[String], decoded: &HashMap<String, DecodedTile>) -> BTreeMap<String, String> {
+    let prefix = format!("{}.", bel.split_once('_').map_or(bel, |(_loc, b)| b));
+    let mut single = BTreeMap::new();
+    let mut diff = BTreeMap::new();
+    let mut diff_used = false;
+    let tiles: Vec<&DecodedTile> = tiles.iter().filter_map(|t| decoded.get(t)).collect();
+    let set = tiles.iter().flat_map(|t| t.fasm.enums.iter().map(|(k, v)| (k, v, true)));
+    let defaults = tiles.iter().flat_map(|t| t.defaults.iter().map(|(k, v)| (k, v, false)));
+    for (name, opt, is_set) in set.chain(defaults) {
+        let setting = match name.strip_prefix(&prefix) {
+            Some(s) => s,
+            None => continue,
+        };
+        let (map, key) = match setting.strip_prefix("DIFFIO18.") {
+            Some(key) => {
+                diff_used |= is_set && key == "BASE_TYPE";
+                (&mut diff, key)
+            }
+            None => (&mut single, setting.strip_prefix("SEIO18.").unwrap_or(setting)),
+        };
+        map.entry(key.to_string()).or_insert_with(|| opt.to_string());
+    }
+    // The differential buffer takes over the pair when used
+    if diff_used { diff } else { single }
+}
+
+fn pin_config(chip: &Chip, pad: &PadData, pin: &str, decoded: &HashMap<String, DecodedTile>) -> PinConfig {
+    let mut cfg = PinConfig {
+        pin: pin.to_string(),
+        pad: pad.name(),
+        bank: if pad.bank >= 0 { Some(pad.bank) } else { None },
+        functions: pad.func.clone(),
+        bel: pad_bel(chip, pad),
+        ..Default::default()
+    };
+    let bel = match &cfg.bel {
+        Some(bel) => bel.to_string(),
+        None => return cfg,
+    };
+    let tiles = chip.tilegroups.get(&bel).cloned().unwrap_or_default();
+    let settings = pin_settings(&bel, &tiles, decoded);
+    let (dir, iostd) = match settings.get("BASE_TYPE").and_then(|t| t.split_once('_')) {
+        Some((dir, iostd)) => (dir.to_string(), iostd.to_string()),
+        None => return cfg,
+    };
+    let voltage = iotype_voltage(&iostd);
+    let first_of = |keys: &[Option<String>]| keys.iter().flatten().find_map(|k| settings.get(k)).cloned();
+    cfg.drive = first_of(&[
+        Some(format!("DRIVE_{}", iostd)),
+        Some(format!("DIFFDRIVE_{}", iostd)),
+        voltage.as_ref().map(|v| format!("DRIVE_{}", v)),
+    ]);
+    cfg.termination = first_of(&[
+        Some("DIFFRESISTOR".to_string()),
+        voltage.as_ref().map(|v| format!("TERMINATION_{}", v)),
+    ]);
+    cfg.slew = settings.get("SLEWRATE").cloned();
+    cfg.pull = settings.get("PULLMODE").cloned();
+    cfg.direction = Some(dir);
+    cfg.iotype = Some(iostd);
+    cfg
+}
+
+// Report the IO configuration of every bonded pin of a package, sorted by pin name
+pub fn io_report(chip: &Chip, db: &mut Database, package: &str) -> Result<Vec<PinConfig>, String> {
+    let iodb = db.device_iodb(&chip.family, &chip.device).clone();
+    let pkg_idx = iodb.package_index(package).ok_or_else(|| {
+        format!(
+            "no package {} for {}, available packages: {}",
+            package,
+            chip.device,
+            iodb.packages.join(", ")
+        )
+    })?;
+    // Bel tilegroups are needed to find the tiles of each PIO
+    let with_groups;
+    let chip = if chip.tilegroups.is_empty() {
+        let mut c = chip.clone();
+        c.create_tilegroups(db);
+        with_groups = c;
+        &with_groups
+    } else {
+        chip
+    };
+    let mut decoded = HashMap::new();
+    let mut pins = Vec::new();
+    for pad in iodb.pads.iter() {
+        let pin = match pad.pins.get(pkg_idx) {
+            Some(pin) if !pin.is_empty() && pin != "-" => pin,
+            _ => continue,
+        };
+        if let Some(bel) = pad_bel(chip, pad) {
+            for tile in chip.tilegroups.get(&bel).into_iter().flatten() {
+                if !decoded.contains_key(tile) {
+                    let t = chip.tile_by_name(tile)?;
+                    let fasm = t.decode_fasm(db);
+                    let tdb = db.tile_bitdb(&chip.family, &t.tiletype);
+                    let defaults = tdb.db.enums.iter().filter_map(|(name, e)| {
+                        let default = e.default.as_ref().or_else(|| {
+                            e.options.iter().find(|(_opt, bits)| bits.is_empty()).map(|(opt, _bits)| opt)
+                        })?;
+                        Some((name.to_string(), default.to_string()))
+                    });
+                    decoded.insert(tile.to_string(), DecodedTile { fasm, defaults: defaults.collect() });
+                }
+            }
+        }
+        pins.push(pin_config(chip, pad, pin, &decoded));
+    }
+    pins.sort_by_key(|p| pin_sort_key(&p.pin));
+    Ok(pins)
+}
+
+// Print the report as a table, with one line per pin
+pub fn write_io_report(out: &mut dyn Write, pins: &[PinConfig]) -> std::io::Result<()> {
+    let opt = |s: &Option<String>| s.as_deref().unwrap_or("-").to_string();
+    writeln!(
+        out,
+        "{:<6} {:<8} {:<4} {:<14} {:<6} {:<6} {:<6} {:<8} {:<6} FUNCTION",
+        "PIN", "PAD", "BANK", "IO_TYPE", "DIR", "DRIVE", "SLEW", "PULL", "TERM"
+    )?;
+    for p in pins.iter() {
+        writeln!(
+            out,
+            "{:<6} {:<8} {:<4} {:<14} {:<6} {:<6} {:<6} {:<8} {:<6} {}",
+            p.pin,
+            p.pad,
+            p.bank.map_or("-".to_string(), |b| b.to_string()),
+            opt(&p.iotype),
+            opt(&p.direction),
+            opt(&p.drive),
+            opt(&p.slew),
+            opt(&p.pull),
+            opt(&p.termination),
+            if p.functions.is_empty() { "-".to_string() } else { p.functions.join("/") }
+        )?;
+    }
+    Ok(())
+}
@@ -133,6 +133,10 @@ impl IPFuzzer {
                                 // Add the enum to the tile data
                                 let iptype_db = db.ip_bitdb(&self.base.family, &self.iptype);
                                 iptype_db.add_enum_option(name, &option, &self.desc, b);
+                                // An option with no changes is the one the base design is in
+                                if delta.is_empty() {
+                                    iptype_db.set_enum_default(name, option);
+                                }
                             }
                         }
                     }
@@ -16,16 +16,26 @@ pub mod bba {
 pub mod bels;
 pub mod bitstream;
 pub mod chip;
+pub mod clocks;
 pub mod database;
 pub mod database_html;
+pub mod diff;
 pub mod docs;
 pub mod fasmparse;
+pub mod flash;
+pub mod image;
+pub mod ioreport;
 pub mod fuzz;
 pub mod ipfuzz;
+pub mod mem;
 pub mod nodecheck;
+pub mod pinout;
 pub mod wires;
 pub mod pip_classes;
+pub mod routing;
 pub mod sites;
+pub mod svf;
+pub mod utilisation;
 pub mod interchange_gen {
 	pub mod routing_graph;
     pub mod writer;
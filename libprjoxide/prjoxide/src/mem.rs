@@ -0,0 +1,232 @@
+use crate::chip::*;
+use crate::database::*;
+
+use std::io::Write;
+
+/*
+Extraction of EBR and LRAM initialisation contents from the IP bus configuration
+
+Each memory is rebuilt from its INITVAL_xx words in the EBR_INIT or LRAM_INIT
+bit database, concatenated in order starting from bit 0 of INITVAL_00. The
+result is split into 40-bit words, matching the bus frame size used for
+memory initialisation.
+*/
+
+// Bits in each memory bus word
+pub const MEM_WORD_BITS: usize = 40;
+
+// LRAM initialisation is defined programmatically, see Chip::configure_ip
+const LRAM_INIT_WORDS: u32 = 128;
+const LRAM_INIT_STRIDE: u32 = 0x280;
+
+pub struct MemoryContents {
+    // IP name, e.g. EBR_WID3 or LRAM_0
+    pub name: String,
+    // 40-bit words, in address order
+    pub words: Vec<u64>,
+}
+
+impl MemoryContents {
+    // Write contents in a format suitable for $readmemh
+    pub fn write_readmemh(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        for w in self.words.iter() {
+            writeln!(out, "{:010X}", w)?;
+        }
+        Ok(())
+    }
+}
+
+// Pack a list of bits into 40-bit words
+fn bits_to_words(bits: &[bool]) -> Vec<u64> {
+    bits.chunks(MEM_WORD_BITS)
+        .map(|c| {
+            c.iter()
+                .enumerate()
+                .fold(0u64, |acc, (i, &b)| if b { acc | (1 << i) } else { acc })
+        })
+        .collect()
+}
+
+// Read the bits of an IP word, from the IP configuration at a given base address
+fn get_word_bits(ch: &Chip, baseaddr: u32, wdata: &ConfigWordData) -> Vec<bool> {
+    wdata
+        .bits
+        .iter()
+        .map(|wb| {
+            wb.iter().all(|cb| {
+                let byte = ch.ipconfig.get(&(baseaddr + cb.frame as u32)).unwrap_or(&0);
+                ((byte >> cb.bit) & 0x1 == 0x1) != cb.invert
+            })
+        })
+        .collect()
+}
+
+// Get the base address and bit database word for each INITVAL_xx of a memory, in order
+fn memory_layout(ch: &Chip, db: &mut Database, name: &str) -> Result<Vec<(u32, ConfigWordData)>, String> {
+    if name.starts_with("EBR_WID") {
+        let baseaddr = ch.get_ip_baseaddr(db, name)?;
+        let tdb = &db.ip_bitdb(&ch.family, "EBR_INIT").db;
+        Ok(tdb
+            .words
+            .iter()
+            .filter(|(k, _v)| k.starts_with("INITVAL_"))
+            .map(|(_k, v)| (baseaddr, v.clone()))
+            .collect())
+    } else if name.starts_with("LRAM_") {
+        let baseaddr = ch.get_ip_baseaddr(db, name)?;
+        let tdb = &db.ip_bitdb(&ch.family, "LRAM_INIT").db;
+        let wdata = tdb.words.get("INITVAL_00").ok_or("no LRAM_INIT word INITVAL_00 in database")?;
+        Ok((0..LRAM_INIT_WORDS)
+            .map(|i| (baseaddr + i * LRAM_INIT_STRIDE, wdata.clone()))
+            .collect())
+    } else {
+        Err(format!("no memory named {}", name))
+    }
+}
+
+// Number of 40-bit words held by an EBR or LRAM
+pub fn memory_words(ch: &Chip, db: &mut Database, name: &str) -> Result<usize, String> {
+    let layout = memory_layout(ch, db, name)?;
+    Ok(layout.iter().map(|(_a, w)| w.bits.len()).sum::<usize>() / MEM_WORD_BITS)
+}
+
+// Names of all EBRs and LRAMs that have initialisation data
+fn initialised_memories(ch: &Chip, db: &mut Database) -> Vec<String> {
+    let regions: Vec<(String, u32, u32)> = db
+        .device_baseaddrs(&ch.family, &ch.device)
+        .regions
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.addr, v.abits))
+        .collect();
+    let mut names = Vec::new();
+    // EBRs: only EBR_WID0 and EBR_WID1 are in the database, the rest follow at the same stride
+    let ebr_base = regions.iter().find(|r| r.0 == "EBR_WID0").map(|r| r.1);
+    let ebr_next = regions.iter().find(|r| r.0 == "EBR_WID1").map(|r| r.1);
+    let lram_regions: Vec<(u32, u32)> = regions
+        .iter()
+        .filter(|r| r.0.starts_with("LRAM_"))
+        .map(|r| (r.1, r.1 + (1 << r.2)))
+        .collect();
+    if let (Some(base), Some(next)) = (ebr_base, ebr_next) {
+        let stride = next - base;
+        let mut wids: Vec<u32> = ch
+            .ipconfig
+            .keys()
+            .filter(|&&a| {
+                a >= base
+                    && ch.get_bus_frame_size(a) == Some(MEM_WORD_BITS / 8)
+                    && !lram_regions.iter().any(|(s, e)| a >= *s && a < *e)
+            })
+            .map(|a| (a - base) / stride)
+            .collect();
+        wids.dedup();
+        names.extend(wids.iter().map(|wid| format!("EBR_WID{}", wid)));
+    }
+    for (name, start, abits) in regions.iter().filter(|r| r.0.starts_with("LRAM_")) {
+        if ch.ipconfig.range(*start..(*start + (1 << abits))).next().is_some() {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+// Rebuild the contents of a single EBR or LRAM
+pub fn read_memory(ch: &Chip, db: &mut Database, name: &str) -> Option<MemoryContents> {
+    let layout = memory_layout(ch, db, name).ok()?;
+    let bits: Vec<bool> = layout
+        .iter()
+        .flat_map(|(baseaddr, wdata)| get_word_bits(ch, *baseaddr, wdata))
+        .collect();
+    Some(MemoryContents {
+        name: name.to_string(),
+        words: bits_to_words(&bits),
+    })
+}
+
+// Rebuild the contents of every EBR and LRAM that has initialisation data
+pub fn extract_memories(ch: &Chip, db: &mut Database) -> Vec<MemoryContents> {
+    initialised_memories(ch, db)
+        .iter()
+        .filter_map(|name| read_memory(ch, db, name))
+        .collect()
+}
+
+// Find an initialised memory whose contents match the given words (zero padded)
+pub fn find_memory_by_contents(ch: &Chip, db: &mut Database, words: &[u64]) -> Result<String, String> {
+    let matches: Vec<String> = extract_memories(ch, db)
+        .into_iter()
+        .filter(|m| {
+            words.len() <= m.words.len()
+                && m.words.iter().enumerate().all(|(i, w)| *w == *words.get(i).unwrap_or(&0))
+        })
+        .map(|m| m.name)
+        .collect();
+    match matches.len() {
+        0 => Err("no memory found with matching contents".to_string()),
+        1 => Ok(matches[0].clone()),
+        _ => Err(format!("contents match more than one memory: {}", matches.join(", "))),
+    }
+}
+
+// Replace the contents of an EBR or LRAM; missing words are set to zero
+pub fn write_memory(ch: &mut Chip, db: &mut Database, name: &str, words: &[u64]) -> Result<(), String> {
+    let layout = memory_layout(ch, db, name)?;
+    let total_bits: usize = layout.iter().map(|(_a, w)| w.bits.len()).sum();
+    if words.len() * MEM_WORD_BITS > total_bits {
+        return Err(format!(
+            "{} words given, but {} only holds {}",
+            words.len(),
+            name,
+            total_bits / MEM_WORD_BITS
+        ));
+    }
+    let mut i = 0;
+    for (baseaddr, wdata) in layout.iter() {
+        for wb in wdata.bits.iter() {
+            let word = words.get(i / MEM_WORD_BITS).unwrap_or(&0);
+            let val = (word >> (i % MEM_WORD_BITS)) & 0x1 == 0x1;
+            for cb in wb.iter() {
+                ch.set_ip_bit(*baseaddr, cb.frame as u32, cb.bit as u32, cb.invert != val);
+            }
+            i += 1;
+        }
+    }
+    Ok(())
+}
+
+// Parse a $readmemh style file into 40-bit words, for a memory of max_words words
+// Supports // comments and @address directives (in words)
+pub fn parse_readmemh(text: &str, max_words: usize) -> Result<Vec<u64>, String> {
+    let mut words = Vec::new();
+    let mut addr = 0;
+    for (lineno, line) in text.lines().enumerate() {
+        let line = line.split("//").next().unwrap();
+        for token in line.split_whitespace() {
+            let token = token.replace('_', "");
+            if let Some(a) = token.strip_prefix('@') {
+                addr = usize::from_str_radix(a, 16)
+                    .map_err(|_| format!("invalid address '{}' on line {}", a, lineno + 1))?;
+                continue;
+            }
+            let value = u64::from_str_radix(&token, 16)
+                .map_err(|_| format!("invalid value '{}' on line {}", token, lineno + 1))?;
+            if value >> MEM_WORD_BITS != 0 {
+                return Err(format!("value '{}' on line {} exceeds 40 bits", token, lineno + 1));
+            }
+            if addr >= max_words {
+                return Err(format!(
+                    "address 0x{:X} on line {} is beyond the end of the memory ({} words)",
+                    addr,
+                    lineno + 1,
+                    max_words
+                ));
+            }
+            if addr >= words.len() {
+                words.resize(addr + 1, 0);
+            }
+            words[addr] = value;
+            addr += 1;
+        }
+    }
+    Ok(words)
+}
@@ -0,0 +1,217 @@
+use crate::database::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::str::FromStr;
+
+/*
+Package pinout export
+
+Lists the bonded IO pins of a package from the IO database, as CSV, JSON or a KiCad
+symbol library. The IO database only covers IO pads (including special IO such as JTAG
+and ADC inputs), so power and ground balls are not included.
+
+Differential pairs are formed by the A/B and C/D PIOs of the same location, with A and
+C the true side. DQS groups are written as in Lattice pinouts: DQ, DQS or DQSN followed
+by the group.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PinoutFormat {
+    Csv,
+    Json,
+    // KiCad symbol library (.kicad_sym), with one unit per bank
+    KiCad,
+}
+
+impl FromStr for PinoutFormat {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(PinoutFormat::Csv),
+            "json" => Ok(PinoutFormat::Json),
+            "kicad" | "kicad_sym" => Ok(PinoutFormat::KiCad),
+            _ => Err(format!(
+                "unknown pinout format '{}' (expected csv, json or kicad)",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PinoutEntry {
+    pub pin: String,
+    pub pad: String,
+    pub bank: Option<i32>,
+    // Pin of the other pad of the differential pair, if bonded
+    pub diff_pair: Option<String>,
+    pub dqs: Option<String>,
+    // VREF function of the pad, e.g. VREF1_1
+    pub vref: Option<String>,
+    pub functions: Vec<String>,
+}
+
+// Natural order of ball and pin names: A2 before A10, and 9 before 10
+pub fn pin_sort_key(pin: &str) -> (usize, String, u32) {
+    let split = pin.find(|c: char| c.is_ascii_digit()).unwrap_or(pin.len());
+    (split, pin[..split].to_string(), pin[split..].parse::<u32>().unwrap_or(0))
+}
+
+fn bonded_pin(pad: &PadData, pkg_idx: usize) -> Option<&str> {
+    match pad.pins.get(pkg_idx) {
+        Some(pin) if !pin.is_empty() && pin != "-" => Some(pin),
+        _ => None,
+    }
+}
+
+// Pinout of a package, sorted by pin name
+pub fn package_pinout(iodb: &DeviceIOData, pkg_idx: usize) -> Vec<PinoutEntry> {
+    let pio_pins: HashMap<(&str, i32, i32), &str> = iodb
+        .pads
+        .iter()
+        .filter(|pad| !pad.side.is_empty() && pad.pio >= 0)
+        .filter_map(|pad| Some(((pad.side.as_str(), pad.offset, pad.pio), bonded_pin(pad, pkg_idx)?)))
+        .collect();
+    let mut pins: Vec<PinoutEntry> = iodb
+        .pads
+        .iter()
+        .filter_map(|pad| {
+            let pin = bonded_pin(pad, pkg_idx)?;
+            let diff_pair = if pad.side.is_empty() || pad.pio < 0 {
+                None
+            } else {
+                pio_pins.get(&(pad.side.as_str(), pad.offset, pad.pio ^ 1))
+            };
+            let dqs = match pad.dqs.as_slice() {
+                [func, group] => Some(format!("{}{}", ["DQ", "DQS", "DQSN"][*func as usize], group)),
+                _ => None,
+            };
+            Some(PinoutEntry {
+                pin: pin.to_string(),
+                pad: pad.name(),
+                bank: if pad.bank >= 0 { Some(pad.bank) } else { None },
+                diff_pair: diff_pair.map(|p| p.to_string()),
+                dqs,
+                vref: if pad.vref >= 0 { Some(format!("VREF{}_{}", pad.bank, pad.vref)) } else { None },
+                functions: pad.func.clone(),
+            })
+        })
+        .collect();
+    pins.sort_by_key(|p| pin_sort_key(&p.pin));
+    pins
+}
+
+pub fn write_pinout_csv(out: &mut dyn Write, pins: &[PinoutEntry]) -> std::io::Result<()> {
+    writeln!(out, "pin,pad,bank,diff_pair,dqs,vref,functions")?;
+    for p in pins.iter() {
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{}",
+            p.pin,
+            p.pad,
+            p.bank.map_or(String::new(), |b| b.to_string()),
+            p.diff_pair.as_deref().unwrap_or(""),
+            p.dqs.as_deref().unwrap_or(""),
+            p.vref.as_deref().unwrap_or(""),
+            p.functions.join("/")
+        )?;
+    }
+    Ok(())
+}
+
+// KiCad grid and text size, in mm
+const GRID: f64 = 2.54;
+const FONT: f64 = 1.27;
+const PIN_LEN: f64 = 5.08;
+
+fn kicad_text(out: &mut dyn Write, indent: &str, kind: &str, text: &str) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "{}({} \"{}\" (effects (font (size {} {}))))",
+        indent, kind, text, FONT, FONT
+    )
+}
+
+// Write a KiCad symbol library containing one symbol, split into a unit per IO bank
+// with special IO in the last unit. Pins are split between the left and right sides.
+pub fn write_pinout_kicad(out: &mut dyn Write, name: &str, pins: &[PinoutEntry]) -> std::io::Result<()> {
+    let mut units: BTreeMap<(bool, i32), Vec<&PinoutEntry>> = BTreeMap::new();
+    for p in pins.iter() {
+        units.entry((p.bank.is_none(), p.bank.unwrap_or(0))).or_default().push(p);
+    }
+    // Place the reference and value above the tallest unit
+    let max_rows = units.values().map(|u| u.len().div_ceil(2)).max().unwrap_or(0);
+    let text_y = (max_rows / 2 + 2) as f64 * GRID;
+    writeln!(out, "(kicad_symbol_lib (version 20211014) (generator prjoxide)")?;
+    writeln!(out, "  (symbol \"{}\" (in_bom yes) (on_board yes)", name)?;
+    for (id, (prop, value)) in [("Reference", "U"), ("Value", name), ("Footprint", ""), ("Datasheet", "")]
+        .iter()
+        .enumerate()
+    {
+        writeln!(
+            out,
+            "    (property \"{}\" \"{}\" (id {}) (at 0 {} 0) (effects (font (size {} {})){}))",
+            prop,
+            value,
+            id,
+            text_y + (1.0 - id as f64) * GRID,
+            FONT,
+            FONT,
+            if id >= 2 { " hide" } else { "" }
+        )?;
+    }
+    for (unit, unit_pins) in units.values().enumerate() {
+        let label = |p: &PinoutEntry| {
+            std::iter::once(p.pad.as_str())
+                .chain(p.functions.iter().map(|f| f.as_str()).filter(|&f| f != p.pad))
+                .collect::<Vec<_>>()
+                .join("/")
+        };
+        let max_label = unit_pins.iter().map(|p| label(p).len()).max().unwrap_or(0);
+        let half_width = ((max_label as f64 * FONT + GRID) / GRID).ceil() * GRID;
+        let rows = unit_pins.len().div_ceil(2);
+        let top = (rows / 2) as f64 * GRID;
+        writeln!(out, "    (symbol \"{}_{}_1\"", name, unit + 1)?;
+        writeln!(
+            out,
+            "      (rectangle (start {} {}) (end {} {}) (stroke (width 0.254) (type default) (color 0 0 0 0)) (fill (type background)))",
+            -half_width,
+            top + GRID,
+            half_width,
+            top - rows as f64 * GRID
+        )?;
+        for (i, p) in unit_pins.iter().enumerate() {
+            let (x, y, angle) = if i < rows {
+                (-half_width - PIN_LEN, top - i as f64 * GRID, 0)
+            } else {
+                (half_width + PIN_LEN, top - (i - rows) as f64 * GRID, 180)
+            };
+            let kind = if p.bank.is_some() { "bidirectional" } else { "passive" };
+            writeln!(out, "      (pin {} line (at {} {} {}) (length {})", kind, x, y, angle, PIN_LEN)?;
+            kicad_text(out, "        ", "name", &label(p))?;
+            kicad_text(out, "        ", "number", &p.pin)?;
+            writeln!(out, "      )")?;
+        }
+        writeln!(out, "    )")?;
+    }
+    writeln!(out, "  )")?;
+    writeln!(out, ")")?;
+    Ok(())
+}
+
+pub fn write_pinout(
+    out: &mut dyn Write,
+    format: PinoutFormat,
+    name: &str,
+    pins: &[PinoutEntry],
+) -> std::io::Result<()> {
+    match format {
+        PinoutFormat::Csv => write_pinout_csv(out, pins),
+        PinoutFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, pins)?;
+            writeln!(out)
+        }
+        PinoutFormat::KiCad => write_pinout_kicad(out, name, pins),
+    }
+}
@@ -0,0 +1,158 @@
+use crate::chip::*;
+use crate::database::*;
+use crate::wires::*;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+/*
+Recovery of routed nets from the configuration of a chip
+
+A whole-chip routing graph is built from the pips and fixed connections of each
+tile, with wires merged across tiles by denormalize_wire. Each net is then traced
+from its driver, following enabled pips and fixed connections, to its sinks.
+
+The driver of a net is found by walking back from the first enabled pip through
+fixed connections, as long as there is only one way back. Sinks are the wires
+the net reaches that have no pips or fixed connections leaving them (usually
+bel pins); routing that is enabled but leads nowhere is not reported as a sink.
+*/
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RoutedPip {
+    pub tile: String,
+    pub from_wire: String,
+    pub to_wire: String,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RoutedNet {
+    pub driver: String,
+    pub sinks: Vec<String>,
+    pub pips: Vec<RoutedPip>,
+}
+
+pub struct RoutingGraph {
+    // Enabled pips, by source wire
+    pips: HashMap<String, Vec<RoutedPip>>,
+    // Destination wires of enabled pips
+    pip_dsts: HashSet<String>,
+    // Fixed connections, forwards and backwards
+    conns_fwd: HashMap<String, Vec<String>>,
+    conns_back: HashMap<String, Vec<String>>,
+    // Wires that are the source of any pip, enabled or not
+    pip_srcs: HashSet<String>,
+}
+
+impl RoutingGraph {
+    // Build the routing graph from the current per-tile CRAM
+    pub fn new(chip: &Chip, db: &mut Database) -> RoutingGraph {
+        let glb = db.device_globals(&chip.family, &chip.device).clone();
+        let mut g = RoutingGraph {
+            pips: HashMap::new(),
+            pip_dsts: HashSet::new(),
+            conns_fwd: HashMap::new(),
+            conns_back: HashMap::new(),
+            pip_srcs: HashSet::new(),
+        };
+        for tile in chip.tiles.iter() {
+            let wire_name = |w: &str| denormalize_wire(chip, &glb, tile, w);
+            for (to_wire, from_wire) in tile.decode_fasm(db).pips.iter() {
+                let pip = RoutedPip {
+                    tile: tile.name.to_string(),
+                    from_wire: wire_name(from_wire),
+                    to_wire: wire_name(to_wire),
+                };
+                g.pip_dsts.insert(pip.to_wire.to_string());
+                g.pips.entry(pip.from_wire.to_string()).or_default().push(pip);
+            }
+            let tdb = &db.tile_bitdb(&chip.family, &tile.tiletype).db;
+            let pip_srcs: BTreeSet<&String> = tdb.pips.values().flatten().map(|p| &p.from_wire).collect();
+            g.pip_srcs.extend(pip_srcs.into_iter().map(|w| wire_name(w)));
+            for (to_wire, conns) in tdb.conns.iter() {
+                let to = wire_name(to_wire);
+                for c in conns.iter() {
+                    let from = wire_name(&c.from_wire);
+                    g.add_conn(&from, &to);
+                    if c.bidir {
+                        g.add_conn(&to, &from);
+                    }
+                }
+            }
+        }
+        g
+    }
+
+    fn add_conn(&mut self, from: &str, to: &str) {
+        self.conns_fwd.entry(from.to_string()).or_default().push(to.to_string());
+        self.conns_back.entry(to.to_string()).or_default().push(from.to_string());
+    }
+
+    // Whether an enabled pip or fixed connection leaves a wire
+    pub fn drives_anything(&self, wire: &str) -> bool {
+        self.pips.contains_key(wire) || self.conns_fwd.contains_key(wire)
+    }
+
+    // Walk back from a wire through fixed connections, while there is only one way back
+    fn find_driver(&self, wire: &str) -> String {
+        let mut curr = wire.to_string();
+        let mut visited = HashSet::new();
+        while visited.insert(curr.to_string()) && !self.pip_dsts.contains(&curr) {
+            match self.conns_back.get(&curr).map(|v| v.as_slice()) {
+                Some([prev]) if !visited.contains(prev) => curr = prev.to_string(),
+                _ => break,
+            }
+        }
+        curr
+    }
+
+    // Trace all nets through the enabled pips, sorted by driver
+    pub fn nets(&self) -> Vec<RoutedNet> {
+        let drivers: BTreeSet<String> = self
+            .pips
+            .keys()
+            .filter(|w| !self.pip_dsts.contains(*w))
+            .map(|w| self.find_driver(w))
+            .collect();
+        let mut visited = HashSet::<String>::new();
+        let mut nets = Vec::new();
+        for driver in drivers.iter() {
+            if visited.contains(driver) {
+                continue;
+            }
+            let mut net = RoutedNet {
+                driver: driver.to_string(),
+                sinks: Vec::new(),
+                pips: Vec::new(),
+            };
+            let mut queue = VecDeque::new();
+            visited.insert(driver.to_string());
+            queue.push_back(driver.to_string());
+            while let Some(wire) = queue.pop_front() {
+                let pips = self.pips.get(&wire).map(|v| v.as_slice()).unwrap_or(&[]);
+                let conns = self.conns_fwd.get(&wire).map(|v| v.as_slice()).unwrap_or(&[]);
+                if pips.is_empty() && conns.is_empty() && !self.pip_srcs.contains(&wire) {
+                    net.sinks.push(wire.to_string());
+                }
+                for pip in pips.iter() {
+                    net.pips.push(pip.clone());
+                    if visited.insert(pip.to_wire.to_string()) {
+                        queue.push_back(pip.to_wire.to_string());
+                    }
+                }
+                for to in conns.iter() {
+                    if visited.insert(to.to_string()) {
+                        queue.push_back(to.to_string());
+                    }
+                }
+            }
+            net.sinks.sort();
+            nets.push(net);
+        }
+        nets
+    }
+}
+
+// Recover the routed nets of a chip
+pub fn trace_nets(chip: &Chip, db: &mut Database) -> Vec<RoutedNet> {
+    RoutingGraph::new(chip, db).nets()
+}
@@ -0,0 +1,129 @@
+use std::io::*;
+
+/*
+SVF generation for programming SRAM over JTAG
+
+The sequence follows the Lattice Nexus SRAM programming flow:
+ - IDCODE check
+ - ISC_ENABLE, to enter programming mode
+ - ISC_ERASE, to clear the SRAM
+ - LSC_INIT_ADDRESS then LSC_BITSTREAM_BURST, with the whole bitstream
+ - LSC_READ_STATUS, polled until DONE is set with busy and fail clear
+ - ISC_DISABLE, to exit programming mode and wake up the device
+
+As the SRAM is erased, this is only suitable for full bitstreams, not partial ones.
+TODO: the sequence, including the 64-bit status polling, has not yet been tested on
+hardware.
+*/
+
+// JTAG instructions (8 bit IR)
+const IDCODE_PUB: u8 = 0xE0;
+const ISC_ENABLE: u8 = 0xC6;
+const ISC_ERASE: u8 = 0x0E;
+const LSC_INIT_ADDRESS: u8 = 0x46;
+const LSC_BITSTREAM_BURST: u8 = 0x7A;
+const LSC_READ_STATUS: u8 = 0x3C;
+const ISC_DISABLE: u8 = 0x26;
+const BYPASS: u8 = 0xFF;
+
+// The status register is 64 bits on Nexus, with the same low bits as ECP5
+// (see the Nexus sysCONFIG user guide, FPGA-TN-02099)
+const STATUS_BITS: usize = 64;
+const STATUS_DONE: u64 = 1 << 8;
+const STATUS_BUSY: u64 = 1 << 12;
+const STATUS_FAIL: u64 = 1 << 13;
+
+// Number of times to read the status register before giving up, 1ms apart
+const STATUS_RETRIES: usize = 100;
+
+// Hex digits per line of bitstream data
+const HEX_PER_LINE: usize = 64;
+
+fn write_sir(out: &mut dyn Write, inst: u8) -> Result<()> {
+    writeln!(out, "SIR\t8\tTDI  ({:02X});", inst)
+}
+
+fn write_runtest(out: &mut dyn Write, tcks: usize, secs: f64) -> Result<()> {
+    writeln!(out, "RUNTEST\tIDLE\t{} TCK\t{:.2E} SEC;", tcks, secs)
+}
+
+// Write an SVF file that loads a bitstream into SRAM
+pub fn write_svf(out: &mut dyn Write, idcode: u32, bitstream: &[u8]) -> Result<()> {
+    writeln!(out, "! Generated by prjoxide")?;
+    writeln!(out)?;
+    writeln!(out, "HDR\t0;")?;
+    writeln!(out, "HIR\t0;")?;
+    writeln!(out, "TDR\t0;")?;
+    writeln!(out, "TIR\t0;")?;
+    writeln!(out, "ENDDR\tDRPAUSE;")?;
+    writeln!(out, "ENDIR\tIRPAUSE;")?;
+    writeln!(out, "STATE\tIDLE;")?;
+    writeln!(out)?;
+
+    writeln!(out, "! Check the IDCODE")?;
+    write_sir(out, IDCODE_PUB)?;
+    writeln!(
+        out,
+        "SDR\t32\tTDI  (00000000)\n\t\tTDO  ({:08X})\n\t\tMASK (FFFFFFFF);",
+        idcode
+    )?;
+    writeln!(out)?;
+
+    writeln!(out, "! Enable programming mode")?;
+    write_sir(out, ISC_ENABLE)?;
+    writeln!(out, "SDR\t8\tTDI  (00);")?;
+    write_runtest(out, 2, 1.0e-2)?;
+    writeln!(out)?;
+
+    writeln!(out, "! Erase SRAM")?;
+    write_sir(out, ISC_ERASE)?;
+    writeln!(out, "SDR\t8\tTDI  (00);")?;
+    write_runtest(out, 2, 1.0e-1)?;
+    writeln!(out)?;
+
+    writeln!(out, "! Program the bitstream")?;
+    write_sir(out, LSC_INIT_ADDRESS)?;
+    writeln!(out, "SDR\t8\tTDI  (01);")?;
+    write_runtest(out, 2, 1.0e-2)?;
+    write_sir(out, LSC_BITSTREAM_BURST)?;
+    write_runtest(out, 2, 1.0e-2)?;
+    // SVF data is written MSB first, but the first bit shifted is the LSB,
+    // whereas the device expects each byte MSB first. So reverse the byte
+    // order and the bits within each byte.
+    let hex: String = bitstream
+        .iter()
+        .rev()
+        .map(|b| format!("{:02X}", b.reverse_bits()))
+        .collect();
+    writeln!(out, "SDR\t{}\tTDI  (", bitstream.len() * 8)?;
+    for line in hex.as_bytes().chunks(HEX_PER_LINE) {
+        writeln!(out, "\t\t{}", std::str::from_utf8(line).unwrap())?;
+    }
+    writeln!(out, "\t\t);")?;
+    write_runtest(out, 100, 1.0e-2)?;
+    writeln!(out)?;
+
+    // LOOP is a Lattice extension to SVF, supported by their tools and most players
+    writeln!(out, "! Wait for DONE to be set, with no failure flagged")?;
+    writeln!(out, "LOOP\t{};", STATUS_RETRIES)?;
+    write_sir(out, LSC_READ_STATUS)?;
+    write_runtest(out, 100, 1.0e-3)?;
+    writeln!(
+        out,
+        "SDR\t{}\tTDI  ({:016X})\n\t\tTDO  ({:016X})\n\t\tMASK ({:016X});",
+        STATUS_BITS,
+        0,
+        STATUS_DONE,
+        STATUS_DONE | STATUS_BUSY | STATUS_FAIL
+    )?;
+    writeln!(out, "ENDLOOP;")?;
+    writeln!(out)?;
+
+    writeln!(out, "! Exit programming mode")?;
+    write_sir(out, ISC_DISABLE)?;
+    write_runtest(out, 2, 1.0e-2)?;
+    write_sir(out, BYPASS)?;
+    write_runtest(out, 100, 1.0e-2)?;
+    writeln!(out, "STATE\tRESET;")?;
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use crate::bels::*;
+use crate::chip::*;
+use crate::database::*;
+use crate::fasmparse::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+/*
+Resource utilisation report
+
+Every bel of the device is found as create_tilegroups does, and the features of its
+tiles decoded. A bel counts as used if its configuration differs from the default for
+an unused bel:
+ - LUT4s: the slice is in CCU2 or DPRAM mode, or the LUT has a non-zero INIT
+ - FFs: SLICEx.REGn.USED is YES
+ - CCU2 (carry) and distributed RAM: the slice MODE is CCU2, or RAMW for the RAM
+   write port that makes a 16x4 LUT RAM
+ - EBR, LRAM, DSP primitives and PLLs: the bel MODE is set to something other than NONE
+ - DCCs: DCCEN is 1 (a bypassed DCC that only routes a clock is not counted)
+ - IO: the PIO (or, for DIFFIO, differential buffer) BASE_TYPE is set
+
+Regions are the clock regions of the global network, named by the spine row and HROW
+column that feed them.
+*/
+
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct ResourceCount {
+    pub used: usize,
+    pub total: usize,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Utilisation {
+    pub device: BTreeMap<String, ResourceCount>,
+    pub regions: BTreeMap<String, BTreeMap<String, ResourceCount>>,
+}
+
+// Decoded settings of the tiles of one bel
+struct BelSettings<'a> {
+    tiles: Vec<&'a FasmTile>,
+}
+
+impl<'a> BelSettings<'a> {
+    fn get_enum(&self, name: &str) -> Option<&'a str> {
+        self.tiles.iter().find_map(|t| t.enums.get(name)).map(|v| v.as_str())
+    }
+    fn enum_set(&self, name: &str) -> bool {
+        self.get_enum(name).is_some_and(|v| v != "NONE")
+    }
+    fn has_word(&self, name: &str) -> bool {
+        self.tiles.iter().any(|t| t.words.contains_key(name))
+    }
+}
+
+// Resources provided by a bel, and whether each is used
+fn bel_resources(bel: &Bel, s: &BelSettings) -> Vec<(String, bool)> {
+    let slice_mode = |slice: &str| s.get_enum(&format!("{}.MODE", slice)).unwrap_or("LOGIC");
+    let name = bel.name.as_str();
+    match bel.beltype.as_str() {
+        "OXIDE_COMB" => {
+            // SLICEA_LUT0
+            let (slice, lut) = name.split_once("_LUT").unwrap();
+            let mode = slice_mode(slice);
+            let used = mode != "LOGIC" || s.has_word(&format!("{}.K{}.INIT", slice, lut));
+            let mut res = vec![("LUT4".to_string(), used)];
+            if lut == "0" {
+                res.push(("CCU2".to_string(), mode == "CCU2"));
+            }
+            res
+        }
+        "OXIDE_FF" => {
+            let (slice, ff) = name.split_once("_FF").unwrap();
+            vec![("FF".to_string(), s.get_enum(&format!("{}.REG{}.USED", slice, ff)) == Some("YES"))]
+        }
+        "RAMW" => {
+            let (slice, _) = name.split_once('_').unwrap();
+            vec![("DPRAM".to_string(), slice_mode(slice) == "RAMW")]
+        }
+        "OXIDE_EBR" => vec![("EBR".to_string(), s.enum_set(&format!("{}.MODE", name)))],
+        "LRAM_CORE" => vec![("LRAM".to_string(), s.enum_set(&format!("{}.MODE", name)))],
+        "PLL_CORE" => vec![("PLL".to_string(), s.enum_set(&format!("{}.MODE", name)))],
+        "PREADD9_CORE" | "MULT9_CORE" | "MULT18_CORE" | "REG18_CORE" | "MULT18X36_CORE" | "ACC54_CORE"
+        | "MULT36_CORE" => {
+            let prim = bel.beltype.trim_end_matches("_CORE");
+            vec![(prim.to_string(), s.enum_set(&format!("{}.MODE", name)))]
+        }
+        "DCC" => vec![("DCC".to_string(), s.get_enum(&format!("{}.DCCEN", name)) == Some("1"))],
+        "SEIO33_CORE" | "SEIO18_CORE" => {
+            let used = s.enum_set(&format!("{}.BASE_TYPE", name))
+                || s.enum_set(&format!("{}.SEIO18.BASE_TYPE", name));
+            vec![("IO".to_string(), used)]
+        }
+        "DIFFIO18_CORE" => vec![("DIFFIO".to_string(), s.enum_set("PIOA.DIFFIO18.BASE_TYPE"))],
+        _ => vec![],
+    }
+}
+
+pub fn utilisation(chip: &Chip, db: &mut Database) -> Result<Utilisation, String> {
+    let globals = db.device_globals(&chip.family, &chip.device).clone();
+    let mut decoded: HashMap<String, FasmTile> = HashMap::new();
+    let mut util = Utilisation::default();
+    for t in chip.tiles.iter() {
+        let bels = get_tile_bels(&t.tiletype, &db.tile_bitdb(&chip.family, &t.tiletype).db);
+        for bel in bels {
+            let bel_tiles = get_bel_tiles(chip, t, &bel);
+            for tile in bel_tiles.iter() {
+                if !decoded.contains_key(tile) {
+                    let ft = chip.tile_by_name(tile)?.decode_fasm(db);
+                    decoded.insert(tile.to_string(), ft);
+                }
+            }
+            let settings = BelSettings {
+                tiles: bel_tiles.iter().map(|x| &decoded[x]).collect(),
+            };
+            let region = globals.clock_region(t.x as i32 + bel.rel_x, t.y as i32 + bel.rel_y);
+            for (res, used) in bel_resources(&bel, &settings) {
+                let add = |count: &mut ResourceCount| {
+                    count.total += 1;
+                    count.used += used as usize;
+                };
+                add(util.device.entry(res.to_string()).or_default());
+                add(util.regions.entry(region.clone()).or_default().entry(res).or_default());
+            }
+        }
+    }
+    Ok(util)
+}
+
+fn write_counts(out: &mut dyn Write, counts: &BTreeMap<String, ResourceCount>) -> std::io::Result<()> {
+    for (res, c) in counts.iter() {
+        let percent = if c.total > 0 { 100.0 * c.used as f64 / c.total as f64 } else { 0.0 };
+        writeln!(out, "    {:<10} {:>7} / {:<7} {:>5.1}%", res, c.used, c.total, percent)?;
+    }
+    Ok(())
+}
+
+// Print device totals, then each region with any used resources
+pub fn write_utilisation(out: &mut dyn Write, device: &str, util: &Utilisation) -> std::io::Result<()> {
+    writeln!(out, "{}:", device)?;
+    write_counts(out, &util.device)?;
+    for (region, counts) in util.regions.iter() {
+        if counts.values().all(|c| c.used == 0) {
+            continue;
+        }
+        writeln!(out, "region {}:", region)?;
+        let used: BTreeMap<String, ResourceCount> =
+            counts.iter().filter(|(_, c)| c.used > 0).map(|(r, c)| (r.to_string(), *c)).collect();
+        write_counts(out, &used)?;
+    }
+    Ok(())
+}
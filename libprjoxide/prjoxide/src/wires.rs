@@ -1,5 +1,7 @@
 // Wire normalisation for Nexus
+use crate::bba::tiletype::Neighbour;
 use crate::chip::*;
+use crate::database::DeviceGlobalsData;
 use regex::Regex;
 
 lazy_static! {
@@ -55,6 +57,60 @@ fn is_full_global_wn(wire: &str) -> bool {
         || DLL_CODE_RE.is_match(wire)
 }
 
+// Levels of the global clock network, from where clocks enter it down to the
+// branches feeding the fabric. Edge clocks are included, although they are not global.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ClockLevel {
+    Eclk,
+    Midmux,
+    Cmux,
+    HrowDriver,
+    Hrow,
+    Spine,
+    Branch,
+}
+
+impl ClockLevel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ClockLevel::Eclk => "eclk",
+            ClockLevel::Midmux => "midmux",
+            ClockLevel::Cmux => "cmux",
+            ClockLevel::HrowDriver => "hrow_driver",
+            ClockLevel::Hrow => "hrow",
+            ClockLevel::Spine => "spine",
+            ClockLevel::Branch => "branch",
+        }
+    }
+}
+
+// Level of the clock network a wire is part of, for canonical (R1C2_HPBX0100),
+// denormalised or global (G:) wire names
+pub fn clock_level(wire: &str) -> Option<ClockLevel> {
+    let wn = match WIRE_RE.captures(wire) {
+        Some(c) => c.get(3).unwrap().as_str(),
+        None => wire,
+    };
+    let wn = wn.rsplit(':').next().unwrap();
+    if GLB_HBRANCH_RE.is_match(wn) {
+        Some(ClockLevel::Branch)
+    } else if GLB_SPINE_RE.is_match(wn) {
+        Some(ClockLevel::Spine)
+    } else if GLB_HROW_RE.is_match(wn) {
+        Some(ClockLevel::Hrow)
+    } else if GLB_HROWD_RE.is_match(wn) {
+        Some(ClockLevel::HrowDriver)
+    } else if GLB_CMUXI_RE.is_match(wn) {
+        Some(ClockLevel::Cmux)
+    } else if GLB_MIDMUX_RE.is_match(wn) {
+        Some(ClockLevel::Midmux)
+    } else if ECLK_RE.is_match(wn) || ECLK_MUXIN_RE.is_match(wn) || ECLK_FEEDBACK_RE.is_match(wn) {
+        Some(ClockLevel::Eclk)
+    } else {
+        None
+    }
+}
+
 pub fn handle_edge_name(
     max_x: i32,
     max_y: i32,
@@ -324,6 +380,34 @@ pub fn normalize_wire(chip: &Chip, tile: &Tile, wire: &str) -> String {
     }
 }
 
+pub fn denormalize_wire(chip: &Chip, glb: &DeviceGlobalsData, tile: &Tile, wire: &str) -> String {
+    /*
+    The inverse of normalize_wire, used to merge the wires of different tiles into one routing graph
+
+    Wires are named R{r}C{c}_{WIRENAME} at the location of the tile they are rooted in, so the
+    same wire seen from two tiles gets the same name. The root of branch, spine and HROW wires
+    is found from the global network structure. Global wires keep their "G:" name.
+
+    DQS group and bank wires have no root location in the database, so are named at the
+    current tile and will not be merged across tiles. Nor are wires whose root would be off
+    the edge of the device.
+    */
+    let (x, y) = (tile.x as usize, tile.y as usize);
+    if wire.starts_with("BANK:") {
+        return format!("R{}C{}_{}", y, x, wire);
+    }
+    let (neigh, base_wire) = Neighbour::parse_wire(wire);
+    let root = match neigh {
+        None => Some((x, y)),
+        Some(Neighbour::Global) | Some(Neighbour::Vcc) => return wire.to_string(),
+        Some(n) => n.resolve(glb, x, y, chip.data.max_col as usize + 1, chip.data.max_row as usize + 1),
+    };
+    match root {
+        Some((rx, ry)) => format!("R{}C{}_{}", ry, rx, base_wire),
+        None => format!("R{}C{}_{}", y, x, wire),
+    }
+}
+
 pub fn is_site_wire(tiletype: &str, wire: &str) -> bool {
     // Return true if a wire is part of a site; false if part of tile routing
     match tiletype {
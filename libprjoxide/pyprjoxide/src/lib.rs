@@ -245,6 +245,25 @@ fn add_always_on_bits(db: &mut Database, empty_bitfile: &str) {
     fuzz::add_always_on_bits(&mut db.db, &empty_chip);
 }
 
+#[pyfunction]
+fn set_dynamic_word(
+    db: &mut Database,
+    family: &str,
+    tiletype: &str,
+    word: &str,
+    enum_name: &str,
+    options: Vec<String>,
+) {
+    fuzz::set_dynamic_word(
+        &mut db.db,
+        family,
+        tiletype,
+        word,
+        enum_name,
+        &options.into_iter().collect(),
+    );
+}
+
 #[pyclass]
 struct Chip {
     c: chip::Chip,
@@ -355,6 +374,7 @@ fn libpyprjoxide(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_wrapped(wrap_pyfunction!(check_nodes))?;
     m.add_wrapped(wrap_pyfunction!(copy_db))?;
     m.add_wrapped(wrap_pyfunction!(add_always_on_bits))?;
+    m.add_wrapped(wrap_pyfunction!(set_dynamic_word))?;
     m.add_wrapped(wrap_pyfunction!(classify_pip))?;
     m.add_wrapped(wrap_pyfunction!(build_sites))?;
     m.add_class::<Database>()?;